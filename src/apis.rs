@@ -1,7 +1,12 @@
 pub mod check_ig_media;
 pub mod create_album;
+pub mod debug_token;
 pub mod delete_object;
+pub mod exchange_long_lived_token;
+pub mod get_app_access_token;
 pub mod get_object;
+pub mod list_page_tokens;
+pub mod paginate;
 pub mod post_album_photo;
 pub mod post_batch;
 pub mod post_feed_array;
@@ -15,5 +20,7 @@ pub mod post_ig_video_stories;
 pub mod post_object;
 pub mod post_picture;
 pub mod post_video;
+pub mod post_video_file;
 pub mod post_video_reel;
 pub mod post_video_thumnail;
+pub mod resume;
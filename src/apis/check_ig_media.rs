@@ -1,42 +1,94 @@
+use crate::flow::Flow;
+use crate::poller::{PollState, Poller};
 use crate::*;
 
-async fn check_ig_media(
-    path: &str,
-    retry_count: usize,
-    client: &reqwest::Client,
-    log: &impl Fn(LogParams),
-) -> Result<serde_json::Value, FbapiError> {
-    let log_params = LogParams::new(&path, &vec![]);
-    let res = execute_retry(
-        retry_count,
-        || async { client.get(path).send().await.map_err(|e| e.into()) },
-        log,
-        log_params,
-    )
-    .await?;
-    Ok(res)
-}
-
 pub(crate) async fn check_ig_media_loop(
-    path: &str,
-    check_retry_count: usize,
-    check_video_delay: usize,
-    retry_count: usize,
-    client: &reqwest::Client,
-    log: &impl Fn(LogParams),
+    api: &Fbapi,
+    credentials: &Credentials,
+    creation_id: &str,
+    policy: PollPolicy,
+    retry: Option<&RetryPolicy>,
+    flow: &Flow,
+    log: &impl Fn(LogEvent),
 ) -> Result<(), FbapiError> {
-    for _ in 0..check_retry_count {
-        let res = check_ig_media(path, retry_count, client, log).await?;
-        let status_code = match res["status_code"].as_str() {
-            Some(s) => s.to_owned(),
-            None => return Err(FbapiError::UnExpected(res)),
+    let request = GraphRequest::get(api.make_path(creation_id))
+        .api("check_ig_media")
+        .query("fields", "status,status_code")
+        .auth(credentials);
+    Poller::new(api, &request, "ig_container", policy, flow)
+        .retry(retry)
+        .exhausted(|_, _| FbapiError::VideoTimeout)
+        .run(
+            |res, _| match res["status_code"].as_str() {
+                Some("FINISHED") => ("FINISHED".to_owned(), PollState::Ready),
+                Some("IN_PROGRESS") => ("IN_PROGRESS".to_owned(), PollState::Pending),
+                Some(status) => (
+                    status.to_owned(),
+                    PollState::Failed(FbapiError::IgVideoError(res.clone())),
+                ),
+                None => (
+                    "".to_owned(),
+                    PollState::Failed(FbapiError::UnExpected(res.clone())),
+                ),
+            },
+            |tick| api.log_poll_status(&request, tick.phase, tick.status, log),
+            log,
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_poll_policy_override() {
+        let api = Fbapi::builder("v19.0")
+            .poll_policy(PollPolicy::fixed(1, Duration::from_millis(1)))
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(
+                        FaultRule::new(Fault::Response {
+                            status: 200,
+                            body: r#"{"id": "18000"}"#.to_owned(),
+                        })
+                        .api("post_ig_image_container"),
+                    )
+                    .rule(FaultRule::new(Fault::StuckStatus)),
+            )
+            .build()
+            .unwrap();
+        let checks = |poll: Option<PollPolicy>| {
+            let api = &api;
+            async move {
+                let events = Arc::new(Mutex::new(vec![]));
+                let log_events = events.clone();
+                let res = api
+                    .upload_ig_picture(
+                        &Credentials::new("EAAtoken"),
+                        "17841",
+                        "https://example.com/a.jpg",
+                        "caption",
+                        poll.as_ref(),
+                        None,
+                        None,
+                        move |event| log_events.lock().unwrap().push(event),
+                    )
+                    .await;
+                assert!(matches!(res, Err(FbapiError::VideoTimeout)));
+                let events = events.lock().unwrap();
+                events
+                    .iter()
+                    .filter(|event| matches!(event, LogEvent::PollStatus { .. }))
+                    .count()
+            }
         };
-        match status_code.as_str() {
-            "FINISHED" => return Ok(()),
-            "IN_PROGRESS" => {}
-            _ => return Err(FbapiError::IgVideoError(res)),
-        }
-        sleep_sec(check_video_delay).await;
+        assert_eq!(checks(None).await, 1);
+        assert_eq!(
+            checks(Some(PollPolicy::fixed(3, Duration::from_millis(1)))).await,
+            3
+        );
     }
-    Err(FbapiError::VideoTimeout)
 }
@@ -1,39 +1,25 @@
+use crate::responses::{parse_raw, AlbumCreated};
 use crate::*;
 
 impl Fbapi {
-    pub async fn create_album(
-        &self,
-        access_token: &str,
-        page_fbid: &str,
-        name: &str,
-        message: &str,
-        retry_count: usize,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let path = self.make_path(&format!("{}/albums", page_fbid));
-        let params = vec![
-            ("access_token", access_token),
-            ("name", name),
-            ("message", message),
-        ];
-        let log_params = LogParams::new(&path, &params);
-        if self.rate_limit_emulation {
-            (log)(log_params);
-            return Err(FbapiError::Facebook((*ERROR_VALUE).clone()));
+    instrument! {
+        api = "create_album", node = page_fbid;
+        pub async fn create_album(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            name: &str,
+            message: &str,
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<AlbumCreated, FbapiError> {
+            let request = GraphRequest::post(self.make_path(&format!("{}/albums", page_fbid)))
+                .api("create_album")
+                .form("name", name)
+                .form("message", message)
+                .auth(credentials);
+            let (res, usage) = self.execute_usage(&request, retry, &log).await?;
+            parse_raw(res, usage)
         }
-        execute_retry(
-            retry_count,
-            || async {
-                self.client
-                    .post(&path)
-                    .form(&params)
-                    .send()
-                    .await
-                    .map_err(|e| e.into())
-            },
-            &log,
-            log_params,
-        )
-        .await
     }
 }
@@ -0,0 +1,24 @@
+use crate::responses::{parse, TokenInfo};
+use crate::*;
+
+impl Fbapi {
+    instrument! {
+        api = "debug_token";
+        /// Inspect `input_token` with `/debug_token`.
+        /// `app_token` is an app access token or a token of an app developer.
+        pub async fn debug_token(
+            &self,
+            input_token: &str,
+            app_token: &Credentials,
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<TokenInfo, FbapiError> {
+            let request = GraphRequest::get(self.make_path("debug_token"))
+                .api("debug_token")
+                .query("input_token", input_token)
+                .auth(app_token);
+            let res = self.execute(&request, retry, &log).await?;
+            parse(res["data"].clone())
+        }
+    }
+}
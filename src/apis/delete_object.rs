@@ -1,29 +1,23 @@
 use crate::*;
 
 impl Fbapi {
-    pub async fn delete_object(
-        &self,
-        access_token: &str,
-        fbid: &str,
-        fields: &str,
-        params: &[(&str, &str)],
-        retry_count: usize,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let query = [("access_token", access_token), ("fields", fields)]
-            .iter()
-            .chain(params)
-            .map(|&(key, value)| format!("{}={}", key, value))
-            .collect::<Vec<_>>()
-            .join("&");
-        let path = self.make_path(&format!("{}?{}", fbid, query));
-        let log_params = LogParams::new(&path, &vec![]);
-        execute_retry(
-            retry_count,
-            || async { self.client.delete(&path).send().await.map_err(|e| e.into()) },
-            &log,
-            log_params,
-        )
-        .await
+    instrument! {
+        api = "delete_object", node = fbid;
+        pub async fn delete_object(
+            &self,
+            credentials: &Credentials,
+            fbid: &str,
+            fields: impl Into<Fields>,
+            params: &[(&str, &str)],
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<serde_json::Value, FbapiError> {
+            let request = GraphRequest::delete(self.make_path(fbid))
+                .api("delete_object")
+                .fields(fields)
+                .queries(params)
+                .auth(credentials);
+            self.execute(&request, retry, &log).await
+        }
     }
 }
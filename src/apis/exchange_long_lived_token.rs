@@ -0,0 +1,27 @@
+use crate::responses::{parse, AccessToken};
+use crate::*;
+
+impl Fbapi {
+    instrument! {
+        api = "exchange_long_lived_token", node = app_id;
+        /// Exchange a short-lived user token for a long-lived one. The secret and the token are
+        /// sent in the POST body so that they stay out of URLs and access logs.
+        pub async fn exchange_long_lived_token(
+            &self,
+            app_id: &str,
+            app_secret: &str,
+            short_lived_token: &str,
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<AccessToken, FbapiError> {
+            let request = GraphRequest::post(self.make_path("oauth/access_token"))
+                .api("exchange_long_lived_token")
+                .form("grant_type", "fb_exchange_token")
+                .form("client_id", app_id)
+                .form("client_secret", app_secret)
+                .form("fb_exchange_token", short_lived_token);
+            let res = self.execute(&request, retry, &log).await?;
+            parse(res)
+        }
+    }
+}
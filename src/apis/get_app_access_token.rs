@@ -0,0 +1,58 @@
+use crate::responses::{parse, AccessToken};
+use crate::*;
+
+impl Fbapi {
+    instrument! {
+        api = "get_app_access_token", node = app_id;
+        /// Get an app access token with the client_credentials grant. The secret is sent in
+        /// the POST body so that it stays out of URLs and access logs.
+        pub async fn get_app_access_token(
+            &self,
+            app_id: &str,
+            app_secret: &str,
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<AccessToken, FbapiError> {
+            let request = GraphRequest::post(self.make_path("oauth/access_token"))
+                .api("get_app_access_token")
+                .form("grant_type", "client_credentials")
+                .form("client_id", app_id)
+                .form("client_secret", app_secret);
+            let res = self.execute(&request, retry, &log).await?;
+            parse(res)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_secret_not_in_url() {
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(FaultInjection::new().rule(FaultRule::new(Fault::Response {
+                status: 200,
+                body: r#"{"access_token":"123|abc","token_type":"bearer"}"#.to_owned(),
+            })))
+            .build()
+            .unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let log_requests = requests.clone();
+        let token = api
+            .get_app_access_token("123", "s3cret", None, move |event| {
+                if let LogEvent::RequestStarted { request, .. } = event {
+                    log_requests.lock().unwrap().push(request);
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(token.access_token, "123|abc");
+        let requests = requests.lock().unwrap();
+        assert!(!requests[0].path.contains("client_secret"));
+        assert!(requests[0]
+            .params
+            .contains(&("client_secret".to_owned(), "***".to_owned())));
+    }
+}
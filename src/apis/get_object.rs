@@ -1,40 +1,45 @@
+use crate::responses::deserialize;
 use crate::*;
+use serde::de::DeserializeOwned;
 
 impl Fbapi {
-    pub async fn get_object(
-        &self,
-        access_token: &str,
-        app_secret: Option<&str>,
-        fbid: &str,
-        fields: &str,
-        params: &[(&str, &str)],
-        retry_count: usize,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let mut query = [("access_token", access_token), ("fields", fields)]
-            .iter()
-            .chain(params)
-            .map(|&(key, value)| format!("{}={}", key, value))
-            .collect::<Vec<_>>()
-            .join("&");
-
-        if let Some(secret) = app_secret {
-            let appsecret_proof = sign(access_token, secret);
-            query += &format!("&{}={}", "appsecret_proof", appsecret_proof);
+    instrument! {
+        api = "get_object", node = fbid;
+        pub async fn get_object(
+            &self,
+            credentials: &Credentials,
+            fbid: &str,
+            fields: impl Into<Fields>,
+            params: &[(&str, &str)],
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<serde_json::Value, FbapiError> {
+            let request = GraphRequest::get(self.make_path(fbid))
+                .api("get_object")
+                .fields(fields)
+                .queries(params)
+                .auth(credentials);
+            self.execute(&request, retry, &log).await
         }
+    }
 
-        let path = self.make_path(&format!("{}?{}", fbid, query));
-        let params = LogParams::new(&path, &vec![]);
-        if self.rate_limit_emulation {
-            (log)(params);
-            return Err(FbapiError::Facebook((*ERROR_VALUE).clone()));
+    instrument! {
+        api = "get_object_as", node = fbid;
+        /// Same as `get_object` but deserializes the response into `T`.
+        /// A mismatch is reported as `FbapiError::Deserialize` with the path of the failing field.
+        pub async fn get_object_as<T: DeserializeOwned>(
+            &self,
+            credentials: &Credentials,
+            fbid: &str,
+            fields: impl Into<Fields>,
+            params: &[(&str, &str)],
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<T, FbapiError> {
+            let res = self
+                .get_object(credentials, fbid, fields, params, retry, log)
+                .await?;
+            deserialize(res)
         }
-        execute_retry(
-            retry_count,
-            || async { self.client.get(&path).send().await.map_err(|e| e.into()) },
-            &log,
-            params,
-        )
-        .await
     }
 }
@@ -0,0 +1,38 @@
+use crate::apis::paginate::PageOptions;
+use crate::responses::{parse, PageToken};
+use crate::*;
+use futures::TryStreamExt;
+
+impl Fbapi {
+    instrument! {
+        api = "list_page_tokens", node = user_id;
+        /// List the pages of `user_id` (usually `me`) with their page access tokens,
+        /// following the `after` cursor until the last page.
+        pub async fn list_page_tokens(
+            &self,
+            credentials: &Credentials,
+            user_id: &str,
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<Vec<PageToken>, FbapiError> {
+            let options = PageOptions {
+                page_size: Some(100),
+                max_items: None,
+            };
+            let pages: Vec<serde_json::Value> = self
+                .paginate(
+                    credentials,
+                    user_id,
+                    "accounts",
+                    "id,name,access_token,category,tasks",
+                    &[],
+                    options,
+                    retry,
+                    log,
+                )
+                .try_collect()
+                .await?;
+            pages.into_iter().map(parse).collect()
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::*;
+use futures::stream::{self, Stream};
+use std::collections::VecDeque;
+
+/// Options for [`Fbapi::paginate`].
+#[derive(Clone, Debug, Default)]
+pub struct PageOptions {
+    /// `limit` sent with every page request.
+    pub page_size: Option<usize>,
+    /// Stop after this many items.
+    pub max_items: Option<usize>,
+}
+
+enum Cursor {
+    Start,
+    Next(Vec<(String, String)>),
+    Done,
+}
+
+struct State<'a, L> {
+    api: &'a Fbapi,
+    credentials: &'a Credentials,
+    path: String,
+    fields: Fields,
+    params: &'a [(&'a str, &'a str)],
+    options: PageOptions,
+    retry: Option<&'a RetryPolicy>,
+    log: L,
+    cursor: Cursor,
+    buffer: VecDeque<serde_json::Value>,
+    count: usize,
+}
+
+impl Fbapi {
+    /// Stream the items of `{node}/{edge}`, following the `after` cursor (or the `next`
+    /// url for other paging styles) until the last page or `options.max_items`.
+    /// Every page goes through the same retry and rate limit handling as `get_object`.
+    pub fn paginate<'a>(
+        &'a self,
+        credentials: &'a Credentials,
+        node: &str,
+        edge: &str,
+        fields: impl Into<Fields>,
+        params: &'a [(&'a str, &'a str)],
+        options: PageOptions,
+        retry: Option<&'a RetryPolicy>,
+        log: impl Fn(LogEvent) + 'a,
+    ) -> impl Stream<Item = Result<serde_json::Value, FbapiError>> + 'a {
+        let state = State {
+            api: self,
+            credentials,
+            path: self.make_path(&format!("{}/{}", node, edge)),
+            fields: fields.into(),
+            params,
+            options,
+            retry,
+            log,
+            cursor: Cursor::Start,
+            buffer: VecDeque::new(),
+            count: 0,
+        };
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(max_items) = state.options.max_items {
+                    if state.count >= max_items {
+                        return None;
+                    }
+                }
+                if let Some(item) = state.buffer.pop_front() {
+                    state.count += 1;
+                    return Some((Ok(item), state));
+                }
+                let cursor = std::mem::replace(&mut state.cursor, Cursor::Done);
+                let request = match cursor {
+                    Cursor::Done => return None,
+                    Cursor::Start => state.first_request(),
+                    Cursor::Next(params) => state.next_request(&params),
+                };
+                match state.fetch(&request).await {
+                    Ok(res) => state.push_page(res),
+                    Err(err) => return Some((Err(err), state)),
+                }
+            }
+        })
+    }
+}
+
+impl<'a, L: Fn(LogEvent)> State<'a, L> {
+    fn first_request(&self) -> GraphRequest {
+        let mut request = GraphRequest::get(self.path.clone())
+            .api("paginate")
+            .fields(&self.fields)
+            .queries(self.params);
+        if let Some(page_size) = self.options.page_size {
+            request = request.query("limit", &page_size.to_string());
+        }
+        request.auth(self.credentials)
+    }
+
+    fn next_request(&self, params: &[(String, String)]) -> GraphRequest {
+        let params: Vec<(&str, &str)> = params
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        GraphRequest::get(self.path.clone())
+            .api("paginate")
+            .queries(&params)
+            .auth(self.credentials)
+    }
+
+    instrument! {
+        api = "paginate", node = self.path, version = self.api.version;
+        async fn fetch(&self, request: &GraphRequest) -> Result<serde_json::Value, FbapiError> {
+            self.api.execute(request, self.retry, &self.log).await
+        }
+    }
+
+    fn push_page(&mut self, res: serde_json::Value) {
+        let data = match res["data"].as_array() {
+            Some(data) => data.clone(),
+            None => return,
+        };
+        if data.is_empty() {
+            return;
+        }
+        self.buffer.extend(data);
+        self.cursor = next_cursor(self, &res);
+    }
+}
+
+fn next_cursor<L>(state: &State<'_, L>, res: &serde_json::Value) -> Cursor {
+    let next = match res["paging"]["next"].as_str() {
+        Some(next) => next,
+        None => return Cursor::Done,
+    };
+    if let Some(after) = res["paging"]["cursors"]["after"].as_str() {
+        let mut params = vec![];
+        if !state.fields.is_empty() {
+            params.push(("fields".to_owned(), state.fields.to_string()));
+        }
+        for (key, value) in state.params {
+            params.push(((*key).to_owned(), (*value).to_owned()));
+        }
+        if let Some(page_size) = state.options.page_size {
+            params.push(("limit".to_owned(), page_size.to_string()));
+        }
+        params.push(("after".to_owned(), after.to_owned()));
+        return Cursor::Next(params);
+    }
+    match next_url_params(next) {
+        Some(params) => Cursor::Next(params),
+        None => Cursor::Done,
+    }
+}
+
+/// Query of a `paging.next` url without the credentials, which are added again per request.
+fn next_url_params(next: &str) -> Option<Vec<(String, String)>> {
+    let url = url::Url::parse(next).ok()?;
+    Some(
+        url.query_pairs()
+            .filter(|(key, _)| key != "access_token" && key != "appsecret_proof")
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_url_params() {
+        let params = next_url_params(
+            "https://graph.facebook.com/v19.0/123/feed?access_token=EAA&fields=id%2Cmessage&limit=25&until=1600000000&__paging_token=abc",
+        )
+        .unwrap();
+        assert_eq!(
+            params,
+            vec![
+                ("fields".to_owned(), "id,message".to_owned()),
+                ("limit".to_owned(), "25".to_owned()),
+                ("until".to_owned(), "1600000000".to_owned()),
+                ("__paging_token".to_owned(), "abc".to_owned()),
+            ]
+        );
+    }
+}
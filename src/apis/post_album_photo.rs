@@ -1,30 +1,28 @@
+use crate::responses::{parse_raw, PhotoUpload};
 use crate::*;
 use reqwest::multipart::Form;
 
 impl Fbapi {
-    pub async fn post_album_photo(
-        &self,
-        access_token: &str,
-        album_fbid: &str,
-        file_path: &str,
-        message: &str,
-        bytes: rusoto_core::ByteStream,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let path = self.make_path(&format!("{}/photos", album_fbid));
-        let params = vec![
-            ("access_token", access_token),
-            ("file_path", file_path),
-            ("message", message),
-            ("published", "true"),
-        ];
-        let log_params = LogParams::new(&path, &params);
-        let part = make_part(file_path, bytes)?;
-        let form = Form::new()
-            .text("access_token", access_token.to_string())
-            .text("message", message.to_string())
-            .text("published", "true")
-            .part("source", part);
-        execute_form(&self.client, &path, form, &log, log_params).await
+    instrument! {
+        api = "post_album_photo", node = album_fbid;
+        pub async fn post_album_photo(
+            &self,
+            credentials: &Credentials,
+            album_fbid: &str,
+            file_path: &str,
+            message: &str,
+            bytes: rusoto_core::ByteStream,
+            log: impl Fn(LogEvent),
+        ) -> Result<PhotoUpload, FbapiError> {
+            let request = GraphRequest::post(self.make_path(&format!("{}/photos", album_fbid)))
+                .api("post_album_photo")
+                .form("message", message)
+                .form("published", "true")
+                .auth(credentials);
+            let bytes = read_bytes(bytes).await?;
+            let form = || Ok(Form::new().part("source", make_part(file_path, &bytes)?));
+            let (res, usage) = self.execute_form(&request, form, &log).await?;
+            parse_raw(res, usage)
+        }
     }
 }
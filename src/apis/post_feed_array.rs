@@ -1,27 +1,185 @@
+use crate::responses::PublishOutcome;
 use crate::*;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 impl Fbapi {
-    pub async fn post_feed_array(
-        &self,
-        page_fbid: &str,
-        params: &[(&str, &str)],
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let path = self.make_path(&format!("{}/feed", page_fbid));
-        let log_params = LogParams::new(&path, &vec![]);
-        execute_retry(
-            0,
-            || async {
-                self.client
-                    .post(&path)
-                    .form(&params)
-                    .send()
-                    .await
-                    .map_err(|e| e.into())
-            },
-            &log,
-            log_params,
+    instrument! {
+        api = "post_feed_array", node = page_fbid;
+        /// Post to the feed of a page. A failed attempt that never reached Facebook is retried
+        /// with `retry`; after any other failure the feed is read back first, and a post with
+        /// the same `message` created since the call began ends the call with
+        /// `PublishOutcome::AlreadyPublished`. Without a `message` a post cannot be told apart
+        /// from others on the page, so nothing is read back and the call is not retried. A
+        /// duplicate post error (code 506) is reported as `AlreadyPublished` as well.
+        pub async fn post_feed_array(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            params: &[(&str, &str)],
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<PublishOutcome<serde_json::Value>, FbapiError> {
+            let path = self.make_path(&format!("{}/feed", page_fbid));
+            let request = GraphRequest::post(path.clone())
+                .api("post_feed_array")
+                .forms(params)
+                .auth(credentials);
+            let no_retry = RetryPolicy::no_retry();
+            let message = params.iter().find(|(key, _)| *key == "message");
+            let (request, retry) = match message {
+                Some((_, message)) => {
+                    let message = (*message).to_owned();
+                    let sent_at = unix_now();
+                    let check = feed_published_check(&path, credentials, sent_at);
+                    let request = request.published_check(check, move |res| {
+                        is_feed_published(res, &message, sent_at)
+                    });
+                    (request, retry)
+                }
+                None => (request, Some(&no_retry)),
+            };
+            PublishOutcome::from_result(self.execute(&request, retry, &log).await)
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+/// Read back the posts of the feed at `path` created since `sent_at`, with unix timestamps.
+fn feed_published_check(path: &str, credentials: &Credentials, sent_at: u64) -> GraphRequest {
+    GraphRequest::get(path.to_owned())
+        .api("check_feed_published")
+        .query("fields", "id,message,created_time")
+        .query("since", &sent_at.to_string())
+        .query("date_format", "U")
+        .auth(credentials)
+}
+
+/// Whether the feed read back has a post with `message` created no earlier than `sent_at`.
+fn is_feed_published(res: &serde_json::Value, message: &str, sent_at: u64) -> bool {
+    res["data"].as_array().is_some_and(|posts| {
+        posts.iter().any(|post| {
+            post["message"] == message
+                && post["created_time"]
+                    .as_u64()
+                    .is_some_and(|created_time| created_time >= sent_at)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api(fault: Fault) -> Fbapi {
+        Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(FaultRule::new(fault).api("post_feed_array").first_calls(1))
+                    .rule(
+                        FaultRule::new(Fault::Response {
+                            status: 200,
+                            body: r#"{"id": "123_456"}"#.to_owned(),
+                        })
+                        .api("post_feed_array"),
+                    )
+                    .rule(
+                        FaultRule::new(Fault::Response {
+                            status: 200,
+                            body: json!({"data": [{
+                                "id": "123_789",
+                                "message": "hello",
+                                "created_time": unix_now(),
+                            }]})
+                            .to_string(),
+                        })
+                        .api("check_feed_published"),
+                    ),
+            )
+            .build()
+            .unwrap()
+    }
+
+    async fn post(api: &Fbapi) -> PublishOutcome<serde_json::Value> {
+        api.post_feed_array(
+            &Credentials::new("EAAtoken"),
+            "123",
+            &[("message", "hello")],
+            Some(&RetryPolicy::default().initial_delay(Duration::from_millis(1))),
+            |_| {},
         )
         .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retry_not_sent() {
+        let api = api(Fault::Timeout(Duration::from_millis(1)));
+        match post(&api).await {
+            PublishOutcome::Published(res) => assert_eq!(res["id"], "123_456"),
+            other => panic!("Expected Published: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_already_published() {
+        let api = api(Fault::graph_error(
+            2,
+            None,
+            "Service temporarily unavailable",
+        ));
+        match post(&api).await {
+            PublishOutcome::AlreadyPublished(res) => assert_eq!(res["data"][0]["id"], "123_789"),
+            other => panic!("Expected AlreadyPublished: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_already_published_after_timeout() {
+        let api = api(Fault::ResponseTimeout(Duration::from_millis(1)));
+        match post(&api).await {
+            PublishOutcome::AlreadyPublished(res) => assert_eq!(res["data"][0]["id"], "123_789"),
+            other => panic!("Expected AlreadyPublished: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_message_is_not_retried() {
+        let api = api(Fault::graph_error(
+            2,
+            None,
+            "Service temporarily unavailable",
+        ));
+        let result = api
+            .post_feed_array(
+                &Credentials::new("EAAtoken"),
+                "123",
+                &[("link", "https://example.com")],
+                Some(&RetryPolicy::default().initial_delay(Duration::from_millis(1))),
+                |_| {},
+            )
+            .await;
+        assert!(matches!(result, Err(FbapiError::Facebook(_))));
+    }
+
+    #[test]
+    fn test_is_feed_published() {
+        let res = json!({"data": [
+            {"id": "1", "message": "hello", "created_time": 1700000000},
+            {"id": "2", "message": "earlier", "created_time": 1699999990},
+        ]});
+        assert!(is_feed_published(&res, "hello", 1700000000));
+        assert!(!is_feed_published(&res, "bye", 1700000000));
+        assert!(!is_feed_published(&res, "earlier", 1700000000));
+        assert!(!is_feed_published(
+            &json!({"data": []}),
+            "hello",
+            1700000000
+        ));
     }
 }
@@ -1,144 +1,113 @@
 use crate::apis::check_ig_media::check_ig_media_loop;
+use crate::responses::{IgMediaPublished, PublishOutcome};
 use crate::validate_media_id;
 use crate::*;
 
 impl Fbapi {
-    pub async fn post_ig_carousel(
-        &self,
-        access_token: &str,
-        account_igid: &str,
-        caption: &str,
-        children: &Vec<String>,
-        check_retry_count: usize,
-        check_video_delay: usize,
-        retry_count: usize,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let creation_id = self
-            .upload_ig_carousel(
-                &access_token,
-                &account_igid,
-                &caption,
-                &children,
-                check_retry_count,
-                check_video_delay,
-                retry_count,
-                &log,
-            )
-            .await?;
+    instrument! {
+        api = "post_ig_carousel", node = account_igid;
+        pub async fn post_ig_carousel(
+            &self,
+            credentials: &Credentials,
+            account_igid: &str,
+            caption: &str,
+            children: &[String],
+            poll: Option<&PollPolicy>,
+            retry: Option<&RetryPolicy>,
+            flow: Option<&FlowOptions>,
+            log: impl Fn(LogEvent),
+        ) -> Result<PublishOutcome<IgMediaPublished>, FbapiError> {
+            let flow = self.start_flow(flow);
+            let creation_id = self
+                .upload_ig_carousel(
+                    credentials,
+                    account_igid,
+                    caption,
+                    children,
+                    poll,
+                    retry,
+                    Some(&flow.options()),
+                    &log,
+                )
+                .await?;
 
-        self.post_ig_media_publish(
-            &access_token,
-            &account_igid,
-            &creation_id,
-            retry_count,
-            &log,
-        )
-        .await
+            flow.step(
+                "publish",
+                self.post_ig_media_publish(credentials, account_igid, &creation_id, retry, &log),
+            )
+            .await
+        }
     }
 
-    /// Upload a carousel and poll its status, returning the creation_id without publishing.
-    /// This allows you to handle the publish step separately using `post_ig_media_publish`.
-    pub async fn upload_ig_carousel(
-        &self,
-        access_token: &str,
-        account_igid: &str,
-        caption: &str,
-        children: &Vec<String>,
-        check_retry_count: usize,
-        check_video_delay: usize,
-        retry_count: usize,
-        log: impl Fn(LogParams),
-    ) -> Result<String, FbapiError> {
-        let creation_id = post(
-            &self.make_path(&format!("{}/media", account_igid)),
-            &access_token,
-            &caption,
-            &children,
-            retry_count,
-            &self.client,
-            &log,
-        )
-        .await?;
+    instrument! {
+        api = "upload_ig_carousel", node = account_igid;
+        /// Upload a carousel and poll its status, returning the creation_id without publishing.
+        /// This allows you to handle the publish step separately using `post_ig_media_publish`.
+        pub async fn upload_ig_carousel(
+            &self,
+            credentials: &Credentials,
+            account_igid: &str,
+            caption: &str,
+            children: &[String],
+            poll: Option<&PollPolicy>,
+            retry: Option<&RetryPolicy>,
+            flow: Option<&FlowOptions>,
+            log: impl Fn(LogEvent),
+        ) -> Result<String, FbapiError> {
+            let flow = self.start_flow(flow);
+            let creation_id = flow
+                .step(
+                    "upload",
+                    post(
+                        &self.make_path(&format!("{}/media", account_igid)),
+                        credentials,
+                        caption,
+                        children,
+                        retry,
+                        self,
+                        &log,
+                    ),
+                )
+                .await?;
 
-        check_ig_media_loop(
-            &self.make_path(&format!(
-                "{}?fields=status,status_code&access_token={}",
-                creation_id, access_token
-            )),
-            check_retry_count,
-            check_video_delay,
-            retry_count,
-            &self.client,
-            &log,
-        )
-        .await?;
+            check_ig_media_loop(
+                self,
+                credentials,
+                &creation_id,
+                self.poll_policy_or(poll),
+                retry,
+                &flow,
+                &log,
+            )
+            .await?;
 
-        Ok(creation_id)
+            Ok(creation_id)
+        }
     }
 }
 
 async fn post(
     path: &str,
-    access_token: &str,
+    credentials: &Credentials,
     caption: &str,
-    children: &Vec<String>,
-    retry_count: usize,
-    client: &reqwest::Client,
-    log: impl Fn(LogParams),
+    children: &[String],
+    retry: Option<&RetryPolicy>,
+    api: &Fbapi,
+    log: impl Fn(LogEvent),
 ) -> Result<String, FbapiError> {
     let children_str = &children.join(",");
     let params = vec![
-        ("access_token", access_token),
         ("media_type", "CAROUSEL"),
         ("children", children_str),
         ("caption", caption),
     ];
-    let log_params = LogParams::new(&path, &params);
-    let res = execute_retry(
-        retry_count,
-        || async {
-            client
-                .post(path)
-                .form(&params)
-                .send()
-                .await
-                .map_err(|e| e.into())
-        },
-        &log,
-        log_params,
-    )
-    .await?;
+    let request = GraphRequest::post(path.to_owned())
+        .api("upload_ig_carousel")
+        .forms(&params)
+        .auth(credentials);
+    let res = api.execute(&request, retry, &log).await?;
     match res["id"].as_str() {
         Some(s) => validate_media_id(s, &res),
         None => Err(FbapiError::UnExpected(res)),
     }
 }
-
-/*
-async fn publish(
-    path: &str,
-    access_token: &str,
-    creation_id: &str,
-    retry_count: usize,
-    client: &reqwest::Client,
-    log: impl Fn(LogParams),
-) -> Result<serde_json::Value, FbapiError> {
-    let params = vec![("access_token", access_token), ("creation_id", creation_id)];
-    let log_params = LogParams::new(&path, &params);
-    execute_retry(
-        retry_count,
-        || async {
-            client
-                .post(path)
-                .form(&params)
-                .send()
-                .await
-                .map_err(|e| e.into())
-        },
-        &log,
-        log_params,
-    )
-    .await
-}
- */
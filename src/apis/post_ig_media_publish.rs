@@ -1,48 +1,123 @@
+use crate::responses::{parse_raw, IgMediaPublished, PublishOutcome};
 use crate::*;
 
 impl Fbapi {
-    pub async fn post_ig_media_publish(
-        &self,
-        access_token: &str,
-        account_igid: &str,
-        creation_id: &str,
-        retry_count: usize,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        post(
-            &self.make_path(&format!("{}/media_publish", account_igid)),
-            &access_token,
-            &creation_id,
-            retry_count,
-            &self.client,
-            &log,
-        )
-        .await
+    instrument! {
+        api = "post_ig_media_publish", node = account_igid;
+        /// Publish the container `creation_id`. Before a retry the container is read back, and
+        /// a `PUBLISHED` status ends the call with `PublishOutcome::AlreadyPublished`.
+        pub async fn post_ig_media_publish(
+            &self,
+            credentials: &Credentials,
+            account_igid: &str,
+            creation_id: &str,
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<PublishOutcome<IgMediaPublished>, FbapiError> {
+            let check = container_published_check(&self.make_path(creation_id), credentials);
+            let result = post(
+                &self.make_path(&format!("{}/media_publish", account_igid)),
+                credentials,
+                creation_id,
+                check,
+                retry,
+                self,
+                &log,
+            )
+            .await;
+            PublishOutcome::from_result(result.and_then(|(res, usage)| parse_raw(res, usage)))
+        }
     }
 }
 
 async fn post(
     path: &str,
-    access_token: &str,
+    credentials: &Credentials,
     creation_id: &str,
-    retry_count: usize,
-    client: &reqwest::Client,
-    log: impl Fn(LogParams),
-) -> Result<serde_json::Value, FbapiError> {
-    let params = vec![("access_token", access_token), ("creation_id", creation_id)];
-    let log_params = LogParams::new(&path, &params);
-    execute_retry(
-        retry_count,
-        || async {
-            client
-                .post(path)
-                .form(&params)
-                .send()
+    check: GraphRequest,
+    retry: Option<&RetryPolicy>,
+    api: &Fbapi,
+    log: impl Fn(LogEvent),
+) -> Result<(serde_json::Value, Usage), FbapiError> {
+    let params = vec![("creation_id", creation_id)];
+    let request = GraphRequest::post(path.to_owned())
+        .api("post_ig_media_publish")
+        .forms(&params)
+        .auth(credentials)
+        .published_check(check, is_container_published);
+    api.execute_usage(&request, retry, &log).await
+}
+
+/// Read back the container at `path` to see whether an earlier publish went through.
+pub(crate) fn container_published_check(path: &str, credentials: &Credentials) -> GraphRequest {
+    GraphRequest::get(path.to_owned())
+        .api("check_ig_media_published")
+        .query("fields", "status_code")
+        .auth(credentials)
+}
+
+pub(crate) fn is_container_published(res: &serde_json::Value) -> bool {
+    res["status_code"] == "PUBLISHED"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_already_published() {
+        let retry = RetryPolicy::default().initial_delay(Duration::from_millis(1));
+        for fault in [
+            Fault::graph_error(2, None, "unavailable"),
+            Fault::ResponseTimeout(Duration::from_millis(1)),
+        ] {
+            let api = Fbapi::builder("v19.0")
+                .fault_injection(
+                    FaultInjection::new()
+                        .rule(FaultRule::new(fault).api("post_ig_media_publish"))
+                        .rule(
+                            FaultRule::new(Fault::Response {
+                                status: 200,
+                                body: r#"{"status_code": "PUBLISHED", "id": "789"}"#.to_owned(),
+                            })
+                            .api("check_ig_media_published"),
+                        ),
+                )
+                .build()
+                .unwrap();
+            let outcome = api
+                .post_ig_media_publish(
+                    &Credentials::new("EAAtoken"),
+                    "123",
+                    "789",
+                    Some(&retry),
+                    |_| {},
+                )
                 .await
-                .map_err(|e| e.into())
-        },
-        &log,
-        log_params,
-    )
-    .await
+                .unwrap();
+            assert!(outcome.is_already_published());
+        }
+
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new().rule(FaultRule::new(Fault::graph_error(
+                    506,
+                    None,
+                    "Duplicate status message",
+                ))),
+            )
+            .build()
+            .unwrap();
+        let outcome = api
+            .post_ig_media_publish(
+                &Credentials::new("EAAtoken"),
+                "123",
+                "789",
+                Some(&retry),
+                |_| {},
+            )
+            .await
+            .unwrap();
+        assert!(outcome.is_already_published());
+    }
 }
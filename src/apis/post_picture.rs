@@ -1,30 +1,28 @@
+use crate::responses::{parse_raw, PhotoUpload};
 use crate::*;
 use reqwest::multipart::Form;
 
 impl Fbapi {
-    pub async fn post_picture(
-        &self,
-        access_token: &str,
-        page_fbid: &str,
-        bytes: rusoto_core::ByteStream,
-        file_path: &str,
-        caption: &str,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let path = self.make_path(&format!("{}/photos", page_fbid));
-        let params = vec![
-            ("access_token", access_token),
-            ("file_path", file_path),
-            ("caption", caption),
-            ("published", "false"),
-        ];
-        let log_params = LogParams::new(&path, &params);
-        let part = make_part(file_path, bytes)?;
-        let form = Form::new()
-            .text("access_token", access_token.to_string())
-            .text("caption", caption.to_string())
-            .text("published", "false")
-            .part("source", part);
-        execute_form(&self.client, &path, form, &log, log_params).await
+    instrument! {
+        api = "post_picture", node = page_fbid;
+        pub async fn post_picture(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            bytes: rusoto_core::ByteStream,
+            file_path: &str,
+            caption: &str,
+            log: impl Fn(LogEvent),
+        ) -> Result<PhotoUpload, FbapiError> {
+            let request = GraphRequest::post(self.make_path(&format!("{}/photos", page_fbid)))
+                .api("post_picture")
+                .form("caption", caption)
+                .form("published", "false")
+                .auth(credentials);
+            let bytes = read_bytes(bytes).await?;
+            let form = || Ok(Form::new().part("source", make_part(file_path, &bytes)?));
+            let (res, usage) = self.execute_form(&request, form, &log).await?;
+            parse_raw(res, usage)
+        }
     }
 }
@@ -1,298 +1,342 @@
+use crate::flow::Flow;
+use crate::poller::{processing_progress, PollState, Poller};
+use crate::responses::{parse_raw, PublishOutcome, PublishedPost};
 use crate::*;
 
 impl Fbapi {
-    pub async fn post_video(
-        &self,
-        access_token: &str,
-        page_fbid: &str,
-        url: &str,
-        description: &str,
-        thumb: Option<rusoto_core::ByteStream>,
-        check_retry_count: usize,
-        check_video_delay: usize,
-        retry_count: usize,
-        long_client: reqwest::Client,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let fbid = self
-            .upload_video(
-                access_token,
-                page_fbid,
-                url,
-                description,
+    instrument! {
+        api = "post_video", node = page_fbid;
+        pub async fn post_video(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            url: &str,
+            description: &str,
+            thumb: Option<rusoto_core::ByteStream>,
+            poll: Option<&PollPolicy>,
+            retry: Option<&RetryPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<PublishOutcome<PublishedPost>, FbapiError> {
+            let flow = self.start_flow(flow);
+            let fbid = self
+                .upload_video(
+                    credentials,
+                    page_fbid,
+                    url,
+                    description,
+                    thumb,
+                    poll,
+                    retry,
+                    Some(&flow.options()),
+                    long_client,
+                    &log,
+                )
+                .await?;
+
+            flow.step(
+                "publish",
+                self.publish_video(credentials, &fbid, false, retry, &log),
+            )
+            .await
+        }
+    }
+
+    instrument! {
+        api = "upload_video", node = page_fbid;
+        /// Upload a video, check its status, and set thumbnail, returning the fbid without publishing.
+        /// This allows you to handle the publish step separately using `publish_video`.
+        pub async fn upload_video(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            url: &str,
+            description: &str,
+            thumb: Option<rusoto_core::ByteStream>,
+            poll: Option<&PollPolicy>,
+            retry: Option<&RetryPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<String, FbapiError> {
+            let flow = self.start_flow(flow);
+            let fbid = flow
+                .step(
+                    "upload",
+                    video(
+                        self,
+                        &self.make_path(&format!("{}/videos", page_fbid)),
+                        credentials,
+                        url,
+                        description,
+                        &long_client,
+                        &log,
+                    ),
+                )
+                .await?;
+            process_video(
+                self,
+                credentials,
+                &fbid,
                 thumb,
-                check_retry_count,
-                check_video_delay,
-                retry_count,
-                long_client,
+                self.poll_policy_or(poll),
+                retry,
+                &flow,
                 &log,
             )
             .await?;
 
-        self.publish_video(access_token, &fbid, false, retry_count, &log)
-            .await
+            Ok(fbid)
+        }
     }
 
-    /// Upload a video, check its status, and set thumbnail, returning the fbid without publishing.
-    /// This allows you to handle the publish step separately using `publish_video`.
-    pub async fn upload_video(
-        &self,
-        access_token: &str,
-        page_fbid: &str,
-        url: &str,
-        description: &str,
-        thumb: Option<rusoto_core::ByteStream>,
-        check_retry_count: usize,
-        check_video_delay: usize,
-        retry_count: usize,
-        long_client: reqwest::Client,
-        log: impl Fn(LogParams),
-    ) -> Result<String, FbapiError> {
-        let fbid = video(
-            &self.make_path(&format!("{}/videos", page_fbid)),
-            access_token,
-            url,
-            description,
-            &long_client,
-            &log,
-        )
-        .await?;
-        check_loop(
-            &self.make_path(&format!(
-                "{}?fields=status&access_token={}",
-                fbid, access_token
-            )),
-            retry_count,
-            check_retry_count,
-            check_video_delay,
-            &self.client,
-            &log,
-        )
-        .await?;
-
-        // サムネルがあれば、サムネル設定します。
-        match thumb {
-            Some(bytes) => {
-                self.post_video_thumnail(access_token, &fbid, bytes, &log)
-                    .await?;
+    instrument! {
+        api = "publish_video", node = fbid;
+        /// Publish a video to newsfeed (and optionally to videos tab first).
+        /// Set `via_videos_tab` to true to publish to videos tab before publishing to newsfeed.
+        /// This allows you to handle errors during publishing while retaining the fbid.
+        /// Before a retry the video is read back; once it is published with a post it is
+        /// reported as `PublishOutcome::AlreadyPublished`.
+        pub async fn publish_video(
+            &self,
+            credentials: &Credentials,
+            fbid: &str,
+            via_videos_tab: bool,
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<PublishOutcome<PublishedPost>, FbapiError> {
+            if via_videos_tab {
+                post_to_videos_tab(&self.make_path(fbid), credentials, retry, self, &log).await?;
             }
-            None => {}
-        };
-
-        Ok(fbid)
-    }
 
-    /// Publish a video to newsfeed (and optionally to videos tab first).
-    /// Set `via_videos_tab` to true to publish to videos tab before publishing to newsfeed.
-    /// This allows you to handle errors during publishing while retaining the fbid.
-    pub async fn publish_video(
-        &self,
-        access_token: &str,
-        fbid: &str,
-        via_videos_tab: bool,
-        retry_count: usize,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        if via_videos_tab {
-            post_to_videos_tab(
-                &self.make_path(&fbid),
-                access_token,
-                retry_count,
-                &self.client,
-                &log,
-            )
-            .await?;
+            let result = post(&self.make_path(fbid), credentials, retry, self, &log)
+                .await
+                .and_then(|(res, usage)| parse_raw::<PublishedPost>(res, usage));
+            PublishOutcome::from_result(result)
         }
-
-        post(
-            &self.make_path(&fbid),
-            access_token,
-            retry_count,
-            &self.client,
-            &log,
-        )
-        .await
     }
 
-    /// 直接 Newsfeed に投稿できない現象が発生している。
-    /// 一度 VideosTab に公開してから Newsfeed に公開する。
-    pub async fn post_video_via_videos_tab(
-        &self,
-        access_token: &str,
-        page_fbid: &str,
-        url: &str,
-        description: &str,
-        thumb: Option<rusoto_core::ByteStream>,
-        check_retry_count: usize,
-        check_video_delay: usize,
-        retry_count: usize,
-        long_client: reqwest::Client,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let fbid = self
-            .upload_video(
-                access_token,
-                page_fbid,
-                url,
-                description,
-                thumb,
-                check_retry_count,
-                check_video_delay,
-                retry_count,
-                long_client,
-                &log,
-            )
-            .await?;
+    instrument! {
+        api = "post_video_via_videos_tab", node = page_fbid;
+        /// 直接 Newsfeed に投稿できない現象が発生している。
+        /// 一度 VideosTab に公開してから Newsfeed に公開する。
+        pub async fn post_video_via_videos_tab(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            url: &str,
+            description: &str,
+            thumb: Option<rusoto_core::ByteStream>,
+            poll: Option<&PollPolicy>,
+            retry: Option<&RetryPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<PublishOutcome<PublishedPost>, FbapiError> {
+            let flow = self.start_flow(flow);
+            let fbid = self
+                .upload_video(
+                    credentials,
+                    page_fbid,
+                    url,
+                    description,
+                    thumb,
+                    poll,
+                    retry,
+                    Some(&flow.options()),
+                    long_client,
+                    &log,
+                )
+                .await?;
 
-        self.publish_video(access_token, &fbid, true, retry_count, &log)
+            flow.step(
+                "publish",
+                self.publish_video(credentials, &fbid, true, retry, &log),
+            )
             .await
+        }
     }
 
-    /// Publish a video to videos tab first, then to newsfeed.
-    /// This is a convenience wrapper around `publish_video` with `via_videos_tab: true`.
-    pub async fn publish_video_via_videos_tab(
-        &self,
-        access_token: &str,
-        fbid: &str,
-        retry_count: usize,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        self.publish_video(access_token, fbid, true, retry_count, log)
-            .await
+    instrument! {
+        api = "publish_video_via_videos_tab", node = fbid;
+        /// Publish a video to videos tab first, then to newsfeed.
+        /// This is a convenience wrapper around `publish_video` with `via_videos_tab: true`.
+        pub async fn publish_video_via_videos_tab(
+            &self,
+            credentials: &Credentials,
+            fbid: &str,
+            retry: Option<&RetryPolicy>,
+            log: impl Fn(LogEvent),
+        ) -> Result<PublishOutcome<PublishedPost>, FbapiError> {
+            self.publish_video(credentials, fbid, true, retry, log)
+                .await
+        }
     }
 }
 
 async fn video(
+    api: &Fbapi,
     path: &str,
-    access_token: &str,
+    credentials: &Credentials,
     file_url: &str,
     description: &str,
     long_client: &reqwest::Client,
-    log: &impl Fn(LogParams),
+    log: &impl Fn(LogEvent),
 ) -> Result<String, FbapiError> {
-    let params = vec![
-        ("access_token", access_token),
-        ("description", description),
-        ("file_url", file_url),
-        ("published", "true"),
-        ("secret", "true"),
-    ];
-    let log_params = LogParams::new(&path, &params);
-    let res: serde_json::Value = execute_retry(
-        0,
-        || async {
-            long_client
-                .post(path)
-                .form(&params)
-                .send()
-                .await
-                .map_err(|e| e.into())
-        },
-        log,
-        log_params,
-    )
-    .await?;
+    let request = GraphRequest::post(path.to_owned())
+        .api("upload_video")
+        .form("description", description)
+        .form("file_url", file_url)
+        .form("published", "true")
+        .form("secret", "true")
+        .auth(credentials);
+    let res = api
+        .execute_with(long_client, &request, Some(&RetryPolicy::no_retry()), log)
+        .await?;
     match res["id"].as_str() {
         Some(res) => Ok(res.to_owned()),
-        None => {
-            return Err(FbapiError::UnExpected(res));
-        }
+        None => Err(FbapiError::UnExpected(res)),
     }
 }
 
-async fn check(
+async fn post(
     path: &str,
-    retry_count: usize,
-    client: &reqwest::Client,
-    log: &impl Fn(LogParams),
-) -> Result<String, FbapiError> {
-    let log_params = LogParams::new(&path, &vec![]);
-    let res = execute_retry(
-        retry_count,
-        || async { client.get(path).send().await.map_err(|e| e.into()) },
-        log,
-        log_params,
-    )
-    .await?;
-    match res["status"]["video_status"].as_str() {
-        Some(s) => Ok(s.to_owned()),
-        None => return Err(FbapiError::UnExpected(res)),
-    }
+    credentials: &Credentials,
+    retry: Option<&RetryPolicy>,
+    api: &Fbapi,
+    log: &impl Fn(LogEvent),
+) -> Result<(serde_json::Value, Usage), FbapiError> {
+    let request = GraphRequest::post(path.to_owned())
+        .api("publish_video")
+        .form("publish_to_news_feed", "true")
+        .form("fields", "id")
+        .auth(credentials)
+        .published_check(video_published_check(path, credentials), is_video_published);
+    api.execute_usage(&request, retry, log).await
 }
 
-async fn post(
-    path: &str,
-    access_token: &str,
-    retry_count: usize,
-    client: &reqwest::Client,
-    log: &impl Fn(LogParams),
-) -> Result<serde_json::Value, FbapiError> {
-    let params = vec![
-        ("access_token", access_token),
-        ("publish_to_news_feed", "true"),
-        ("fields", "id"),
-    ];
-    let log_params = LogParams::new(path, &params);
-    execute_retry(
-        retry_count,
-        || async {
-            client
-                .post(path)
-                .form(&params)
-                .send()
-                .await
-                .map_err(|e| e.into())
-        },
-        log,
-        log_params,
-    )
-    .await
+/// Read back the video at `path` to see whether an earlier publish went through.
+pub(crate) fn video_published_check(path: &str, credentials: &Credentials) -> GraphRequest {
+    GraphRequest::get(path.to_owned())
+        .api("check_video_published")
+        .query("fields", "published,post_id")
+        .auth(credentials)
+}
+
+pub(crate) fn is_video_published(res: &serde_json::Value) -> bool {
+    res["published"] == true && res["post_id"].is_string()
 }
 
 async fn post_to_videos_tab(
     path: &str,
-    access_token: &str,
-    retry_count: usize,
-    client: &reqwest::Client,
-    log: &impl Fn(LogParams),
+    credentials: &Credentials,
+    retry: Option<&RetryPolicy>,
+    api: &Fbapi,
+    log: &impl Fn(LogEvent),
 ) -> Result<serde_json::Value, FbapiError> {
-    let params = vec![
-        ("access_token", access_token),
-        ("publish_to_videos_tab", "true"),
-        ("fields", "id"),
-    ];
-    let log_params = LogParams::new(path, &params);
-    execute_retry(
-        retry_count,
-        || async {
-            client
-                .post(path)
-                .form(&params)
-                .send()
-                .await
-                .map_err(|e| e.into())
-        },
-        log,
-        log_params,
-    )
-    .await
+    let request = GraphRequest::post(path.to_owned())
+        .api("publish_video_via_videos_tab")
+        .form("publish_to_videos_tab", "true")
+        .form("fields", "id")
+        .auth(credentials);
+    api.execute(&request, retry, log).await
 }
 
-async fn check_loop(
-    path: &str,
-    retry_count: usize,
-    check_retry_count: usize,
-    check_video_delay: usize,
-    client: &reqwest::Client,
-    log: &impl Fn(LogParams),
+/// Wait for an uploaded video to be processed, then set its thumbnail.
+pub(crate) async fn process_video(
+    api: &Fbapi,
+    credentials: &Credentials,
+    fbid: &str,
+    thumb: Option<rusoto_core::ByteStream>,
+    policy: PollPolicy,
+    retry: Option<&RetryPolicy>,
+    flow: &Flow,
+    log: &impl Fn(LogEvent),
 ) -> Result<(), FbapiError> {
-    for _ in 0..check_retry_count {
-        match check(path, retry_count, client, log).await?.as_str() {
-            "ready" => return Ok(()),
-            "processing" => {}
-            _ => return Err(FbapiError::VideoError),
+    check_loop(api, credentials, fbid, retry, policy, flow, log).await?;
+
+    // サムネルがあれば、サムネル設定します。
+    if let Some(bytes) = thumb {
+        flow.step(
+            "thumbnail",
+            api.post_video_thumnail(credentials, fbid, bytes, log),
+        )
+        .await?;
+    };
+    Ok(())
+}
+
+pub(crate) async fn check_loop(
+    api: &Fbapi,
+    credentials: &Credentials,
+    fbid: &str,
+    retry: Option<&RetryPolicy>,
+    policy: PollPolicy,
+    flow: &Flow,
+    log: &impl Fn(LogEvent),
+) -> Result<(), FbapiError> {
+    let request = GraphRequest::get(api.make_path(fbid))
+        .api("check_video")
+        .query("fields", "status")
+        .auth(credentials);
+    Poller::new(api, &request, "video_processing", policy, flow)
+        .retry(retry)
+        .percent(processing_progress)
+        .exhausted(|_, _| FbapiError::VideoTimeout)
+        .run(
+            |res, _| match res["status"]["video_status"].as_str() {
+                Some("ready") => ("ready".to_owned(), PollState::Ready),
+                Some("processing") => ("processing".to_owned(), PollState::Pending),
+                Some(status) => (status.to_owned(), PollState::Failed(FbapiError::VideoError)),
+                None => (
+                    "".to_owned(),
+                    PollState::Failed(FbapiError::UnExpected(res.clone())),
+                ),
+            },
+            |tick| api.log_poll_status(&request, tick.phase, tick.status, log),
+            log,
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_already_published_after_timeout() {
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(
+                        FaultRule::new(Fault::ResponseTimeout(Duration::from_millis(1)))
+                            .api("publish_video"),
+                    )
+                    .rule(
+                        FaultRule::new(Fault::Response {
+                            status: 200,
+                            body: r#"{"published": true, "post_id": "123_456"}"#.to_owned(),
+                        })
+                        .api("check_video_published"),
+                    ),
+            )
+            .build()
+            .unwrap();
+        let outcome = api
+            .publish_video(
+                &Credentials::new("EAAtoken"),
+                "456",
+                false,
+                Some(&RetryPolicy::default().initial_delay(Duration::from_millis(1))),
+                |_| {},
+            )
+            .await
+            .unwrap();
+        match outcome {
+            PublishOutcome::AlreadyPublished(res) => assert_eq!(res["post_id"], "123_456"),
+            other => panic!("Expected AlreadyPublished: {:?}", other),
         }
-        sleep_sec(check_video_delay).await;
     }
-    Err(FbapiError::VideoTimeout)
 }
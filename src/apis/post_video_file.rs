@@ -0,0 +1,575 @@
+use crate::apis::post_video::process_video;
+use crate::flow::Flow;
+use crate::responses::{PublishOutcome, PublishedPost};
+use crate::*;
+use futures::StreamExt;
+use reqwest::multipart::{Form, Part};
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Video sent in chunks by `upload_video_file`.
+pub enum VideoSource {
+    /// Local file, read one chunk at a time.
+    File(PathBuf),
+    /// Video already in memory.
+    Bytes(Vec<u8>),
+    /// Stream of `size` bytes, read once from the start. Only the current chunk is kept, so
+    /// the upload fails if Facebook asks again for an offset before it.
+    Stream {
+        stream: rusoto_core::ByteStream,
+        size: u64,
+    },
+}
+
+impl Fbapi {
+    instrument! {
+        api = "post_video_file", node = page_fbid;
+        pub async fn post_video_file(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            source: VideoSource,
+            description: &str,
+            thumb: Option<rusoto_core::ByteStream>,
+            poll: Option<&PollPolicy>,
+            retry: Option<&RetryPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<PublishOutcome<PublishedPost>, FbapiError> {
+            let flow = self.start_flow(flow);
+            let fbid = self
+                .upload_video_file(
+                    credentials,
+                    page_fbid,
+                    source,
+                    description,
+                    thumb,
+                    poll,
+                    retry,
+                    Some(&flow.options()),
+                    long_client,
+                    &log,
+                )
+                .await?;
+
+            flow.step(
+                "publish",
+                self.publish_video(credentials, &fbid, false, retry, &log),
+            )
+            .await
+        }
+    }
+
+    instrument! {
+        api = "upload_video_file", node = page_fbid;
+        /// Upload a local video in chunks to graph-video (`upload_phase` start, transfer and
+        /// finish), check its status and set the thumbnail, returning the fbid without publishing.
+        /// Each chunk is retried with `retry`; when Facebook answers a chunk with the offsets it
+        /// expects, the transfer continues from its `start_offset`, and a `start_offset` that
+        /// does not move forward fails the upload. The session is reported with each chunk so
+        /// that `resume_video_file` can continue it.
+        pub async fn upload_video_file(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            source: VideoSource,
+            description: &str,
+            thumb: Option<rusoto_core::ByteStream>,
+            poll: Option<&PollPolicy>,
+            retry: Option<&RetryPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<String, FbapiError> {
+            let flow = self.start_flow(flow);
+            let path = self.make_video_path(&format!("{}/videos", page_fbid));
+            let (reader, file_size) = ChunkReader::open(source).await?;
+
+            // １．アップロードセッションを開始する。
+            let start_request = GraphRequest::post(path.clone())
+                .api("upload_video_file")
+                .form("upload_phase", "start")
+                .form("file_size", &file_size.to_string())
+                .auth(credentials);
+            let res = flow
+                .step(
+                    "upload",
+                    self.execute_with(
+                        &long_client,
+                        &start_request,
+                        Some(&RetryPolicy::no_retry()),
+                        &log,
+                    ),
+                )
+                .await?;
+            let session = match (
+                res["video_id"].as_str(),
+                res["upload_session_id"].as_str(),
+                parse_offsets(&res),
+            ) {
+                (Some(video_id), Some(session_id), Some((start_offset, end_offset))) => {
+                    UploadSession {
+                        video_id: video_id.to_owned(),
+                        upload_session_id: session_id.to_owned(),
+                        start_offset,
+                        end_offset,
+                    }
+                }
+                _ => return Err(FbapiError::UnExpected(res)),
+            };
+
+            transfer_video_file(
+                self,
+                credentials,
+                &path,
+                reader,
+                file_size,
+                session,
+                description,
+                thumb,
+                self.poll_policy_or(poll),
+                retry,
+                &flow,
+                long_client,
+                &log,
+            )
+            .await
+        }
+    }
+
+    instrument! {
+        api = "resume_video_file", node = page_fbid;
+        /// Continue a chunked upload of `upload_video_file` from `upload`, as recorded in
+        /// [`PublishHandle::upload`], instead of starting a new session. `source` must be the
+        /// same video; it is read from the offset Facebook expects. The upload is then finished
+        /// and processed like `upload_video_file`, returning the fbid without publishing.
+        pub async fn resume_video_file(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            source: VideoSource,
+            description: &str,
+            thumb: Option<rusoto_core::ByteStream>,
+            upload: &UploadSession,
+            poll: Option<&PollPolicy>,
+            retry: Option<&RetryPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<String, FbapiError> {
+            let flow = self.start_flow(flow);
+            let path = self.make_video_path(&format!("{}/videos", page_fbid));
+            let (reader, file_size) = ChunkReader::open(source).await?;
+            transfer_video_file(
+                self,
+                credentials,
+                &path,
+                reader,
+                file_size,
+                upload.clone(),
+                description,
+                thumb,
+                self.poll_policy_or(poll),
+                retry,
+                &flow,
+                long_client,
+                &log,
+            )
+            .await
+        }
+    }
+}
+
+/// Send the chunks Facebook asks for from `session`, finish the upload and process the
+/// video. Each range is reported with the session so that the handle can resume it.
+async fn transfer_video_file(
+    api: &Fbapi,
+    credentials: &Credentials,
+    path: &str,
+    mut reader: ChunkReader,
+    file_size: u64,
+    mut session: UploadSession,
+    description: &str,
+    thumb: Option<rusoto_core::ByteStream>,
+    policy: PollPolicy,
+    retry: Option<&RetryPolicy>,
+    flow: &Flow,
+    long_client: reqwest::Client,
+    log: &impl Fn(LogEvent),
+) -> Result<String, FbapiError> {
+    let report = |session: &UploadSession| {
+        flow.report_upload(
+            "upload",
+            "transfer",
+            Some((session.start_offset * 100 / file_size.max(1)).min(100) as u8),
+            Some(session.clone()),
+        )
+    };
+    report(&session);
+
+    // ２．Facebook が指定する範囲ごとに動画を送信する。
+    while session.start_offset < session.end_offset {
+        let (start, end) = (session.start_offset, session.end_offset);
+        let chunk = reader.read(start, end).await?;
+        let request = GraphRequest::post(path.to_owned())
+            .api("upload_video_file_chunk")
+            .form("upload_phase", "transfer")
+            .form("upload_session_id", &session.upload_session_id)
+            .form("start_offset", &start.to_string())
+            .auth(credentials);
+        let result = flow
+            .run(
+                "upload",
+                None,
+                api.execute_form_with(
+                    &long_client,
+                    &request,
+                    || {
+                        let part = Part::bytes(chunk.clone()).file_name("video_file_chunk");
+                        Ok(Form::new().part("video_file_chunk", part))
+                    },
+                    retry,
+                    log,
+                ),
+            )
+            .await;
+        let (start_offset, end_offset) = match result {
+            // 次の start_offset が進まない場合は、同じ範囲を送り続けないようにエラーにする。
+            Ok((res, _)) => match parse_offsets(&res) {
+                Some(next) if next.0 > start => next,
+                _ => return Err(FbapiError::UnExpected(res)),
+            },
+            // 送信済みの範囲が食い違う場合は、Facebook が指定するオフセットから再開する。
+            Err(FbapiError::Facebook(error)) => {
+                match parse_offsets(&error["error"]["error_data"]) {
+                    Some(expected) if expected != (start, end) => expected,
+                    _ => return Err(FbapiError::Facebook(error)),
+                }
+            }
+            Err(error) => return Err(error),
+        };
+        session.start_offset = start_offset;
+        session.end_offset = end_offset;
+        report(&session);
+    }
+
+    // ３．アップロードセッションを終了する。
+    let finish_request = GraphRequest::post(path.to_owned())
+        .api("upload_video_file")
+        .form("upload_phase", "finish")
+        .form("upload_session_id", &session.upload_session_id)
+        .form("description", description)
+        .form("published", "true")
+        .form("secret", "true")
+        .auth(credentials);
+    let res = flow
+        .run(
+            "upload",
+            None,
+            api.execute_with(
+                &long_client,
+                &finish_request,
+                Some(&RetryPolicy::no_retry()),
+                log,
+            ),
+        )
+        .await?;
+    if res["success"].as_bool() != Some(true) {
+        return Err(FbapiError::UnExpected(res));
+    }
+    flow.report("upload", "finished", Some(100));
+
+    process_video(
+        api,
+        credentials,
+        &session.video_id,
+        thumb,
+        policy,
+        retry,
+        flow,
+        log,
+    )
+    .await?;
+
+    Ok(session.video_id)
+}
+
+/// `start_offset` and `end_offset` of a response; Facebook sends them as strings.
+fn parse_offsets(res: &serde_json::Value) -> Option<(u64, u64)> {
+    let offset = |value: &serde_json::Value| match value {
+        serde_json::Value::String(s) => s.parse().ok(),
+        value => value.as_u64(),
+    };
+    Some((offset(&res["start_offset"])?, offset(&res["end_offset"])?))
+}
+
+enum ChunkReader {
+    File(tokio::fs::File),
+    Bytes(Vec<u8>),
+    Stream {
+        stream: rusoto_core::ByteStream,
+        /// Bytes read from the stream, starting at `position`.
+        buffer: Vec<u8>,
+        position: u64,
+    },
+}
+
+impl ChunkReader {
+    /// Reader of `source` and its size.
+    async fn open(source: VideoSource) -> Result<(Self, u64), FbapiError> {
+        match source {
+            VideoSource::File(path) => {
+                let file = tokio::fs::File::open(path).await?;
+                let size = file.metadata().await?.len();
+                Ok((ChunkReader::File(file), size))
+            }
+            VideoSource::Bytes(bytes) => {
+                let size = bytes.len() as u64;
+                Ok((ChunkReader::Bytes(bytes), size))
+            }
+            VideoSource::Stream { stream, size } => Ok((
+                ChunkReader::Stream {
+                    stream,
+                    buffer: vec![],
+                    position: 0,
+                },
+                size,
+            )),
+        }
+    }
+
+    /// Bytes from `start` up to `end`, fewer at the end of the video.
+    async fn read(&mut self, start: u64, end: u64) -> Result<Vec<u8>, FbapiError> {
+        match self {
+            ChunkReader::File(file) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                let mut chunk = vec![];
+                (&mut *file)
+                    .take(end - start)
+                    .read_to_end(&mut chunk)
+                    .await?;
+                Ok(chunk)
+            }
+            ChunkReader::Bytes(bytes) => {
+                let len = bytes.len() as u64;
+                Ok(bytes[start.min(len) as usize..end.min(len) as usize].to_vec())
+            }
+            ChunkReader::Stream {
+                stream,
+                buffer,
+                position,
+            } => {
+                if start < *position {
+                    return Err(FbapiError::UnExpected(json!({
+                        "error": "start_offset before the buffered chunk",
+                        "start_offset": start,
+                        "position": *position,
+                    })));
+                }
+                while *position + (buffer.len() as u64) < end {
+                    match stream.next().await {
+                        Some(bytes) => buffer.extend_from_slice(&bytes?),
+                        None => break,
+                    }
+                }
+                let skip = ((start - *position) as usize).min(buffer.len());
+                buffer.drain(..skip);
+                *position += skip as u64;
+                let len = ((end - start) as usize).min(buffer.len());
+                Ok(buffer[..len].to_vec())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn response(body: serde_json::Value) -> Fault {
+        Fault::Response {
+            status: 200,
+            body: body.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_video_file() {
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(
+                        FaultRule::new(Fault::graph_error(
+                            2,
+                            None,
+                            "Service temporarily unavailable",
+                        ))
+                        .api("upload_video_file_chunk")
+                        .first_calls(1),
+                    )
+                    .rule(
+                        FaultRule::new(response(json!({
+                            "video_id": "456",
+                            "upload_session_id": "789",
+                            "start_offset": "0",
+                            "end_offset": "10",
+                            "success": true,
+                        })))
+                        .api("upload_video_file"),
+                    )
+                    .rule(
+                        FaultRule::new(response(json!({"start_offset": "10", "end_offset": "10"})))
+                            .api("upload_video_file_chunk"),
+                    )
+                    .rule(
+                        FaultRule::new(response(json!({"status": {"video_status": "ready"}})))
+                            .api("check_video"),
+                    ),
+            )
+            .build()
+            .unwrap();
+        let events = Arc::new(Mutex::new(vec![]));
+        let log_events = events.clone();
+        let fbid = api
+            .upload_video_file(
+                &Credentials::new("EAAtoken"),
+                "123",
+                VideoSource::Bytes(b"0123456789".to_vec()),
+                "description",
+                None,
+                None,
+                Some(&RetryPolicy::default().initial_delay(Duration::from_millis(1))),
+                None,
+                reqwest::Client::new(),
+                move |event| log_events.lock().unwrap().push(event),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fbid, "456");
+        let retries = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, LogEvent::RetryScheduled { .. }))
+            .count();
+        assert_eq!(retries, 1);
+    }
+
+    #[tokio::test]
+    async fn test_offset_not_increasing() {
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(
+                        FaultRule::new(response(json!({
+                            "video_id": "456",
+                            "upload_session_id": "789",
+                            "start_offset": "0",
+                            "end_offset": "5",
+                        })))
+                        .api("upload_video_file"),
+                    )
+                    .rule(
+                        FaultRule::new(response(json!({"start_offset": "0", "end_offset": "5"})))
+                            .api("upload_video_file_chunk"),
+                    ),
+            )
+            .build()
+            .unwrap();
+        let result = api
+            .upload_video_file(
+                &Credentials::new("EAAtoken"),
+                "123",
+                VideoSource::Bytes(b"0123456789".to_vec()),
+                "description",
+                None,
+                None,
+                None,
+                None,
+                reqwest::Client::new(),
+                |_| {},
+            )
+            .await;
+        assert!(matches!(result, Err(FbapiError::UnExpected(_))));
+    }
+
+    #[tokio::test]
+    async fn test_resume_video_file() {
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(
+                        FaultRule::new(response(json!({"success": true}))).api("upload_video_file"),
+                    )
+                    .rule(
+                        FaultRule::new(response(json!({"start_offset": "10", "end_offset": "10"})))
+                            .api("upload_video_file_chunk"),
+                    )
+                    .rule(
+                        FaultRule::new(response(json!({"status": {"video_status": "ready"}})))
+                            .api("check_video"),
+                    ),
+            )
+            .build()
+            .unwrap();
+        let handle = Arc::new(Mutex::new(PublishHandle::facebook_video(
+            "123", "456", false,
+        )));
+        let recorded = handle.clone();
+        let flow = FlowOptions::default()
+            .progress(move |progress| recorded.lock().unwrap().record(&progress));
+        let upload = UploadSession {
+            video_id: "456".to_owned(),
+            upload_session_id: "789".to_owned(),
+            start_offset: 5,
+            end_offset: 10,
+        };
+        handle.lock().unwrap().upload = Some(upload.clone());
+        let fbid = api
+            .resume_video_file(
+                &Credentials::new("EAAtoken"),
+                "123",
+                VideoSource::Bytes(b"0123456789".to_vec()),
+                "description",
+                None,
+                &upload,
+                None,
+                None,
+                Some(&flow),
+                reqwest::Client::new(),
+                |_| {},
+            )
+            .await
+            .unwrap();
+        assert_eq!(fbid, "456");
+        let handle = handle.lock().unwrap();
+        assert_eq!(handle.upload, None);
+        assert_eq!(handle.phase, PublishPhase::Ready);
+    }
+
+    #[tokio::test]
+    async fn test_read_chunks() {
+        assert_eq!(
+            parse_offsets(&json!({"start_offset": "1048576", "end_offset": 2097152})),
+            Some((1048576, 2097152))
+        );
+        let stream = futures::stream::iter(vec![
+            Ok::<_, std::io::Error>(b"0123".to_vec().into()),
+            Ok(b"456789".to_vec().into()),
+        ]);
+        let (mut reader, size) = ChunkReader::open(VideoSource::Stream {
+            stream: rusoto_core::ByteStream::new(stream),
+            size: 10,
+        })
+        .await
+        .unwrap();
+        assert_eq!(size, 10);
+        assert_eq!(reader.read(0, 6).await.unwrap(), b"012345");
+        assert_eq!(reader.read(3, 8).await.unwrap(), b"34567");
+        assert_eq!(reader.read(8, 16).await.unwrap(), b"89");
+        assert!(reader.read(2, 4).await.is_err());
+    }
+}
@@ -1,389 +1,411 @@
-use serde_json::Value;
+use crate::flow::Flow;
+use crate::poller::{processing_progress, PollState, Poller};
+use crate::responses::{parse_raw, ReelPublished};
 
 use crate::*;
 
 impl Fbapi {
-    pub async fn post_video_reel(
-        &self,
-        access_token: &str,
-        page_fbid: &str,
-        file_url: &str,
-        description: &str,
-        thumb: Option<rusoto_core::ByteStream>,
-        long_client: reqwest::Client,
-        log: impl Fn(LogParams),
-    ) -> Result<Value, FbapiError> {
-        let video_id = self
-            .upload_video_reel(
-                access_token,
+    instrument! {
+        api = "post_video_reel", node = page_fbid;
+        pub async fn post_video_reel(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            file_url: &str,
+            description: &str,
+            thumb: Option<rusoto_core::ByteStream>,
+            poll: Option<&PollPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<ReelPublished, FbapiError> {
+            let flow = self.start_flow(flow);
+            let video_id = self
+                .upload_video_reel(
+                    credentials,
+                    page_fbid,
+                    file_url,
+                    thumb,
+                    poll,
+                    Some(&flow.options()),
+                    long_client.clone(),
+                    &log,
+                )
+                .await?;
+
+            self.publish_video_reel(
+                credentials,
                 page_fbid,
-                file_url,
-                thumb,
-                long_client.clone(),
+                &video_id,
+                description,
+                poll,
+                Some(&flow.options()),
+                long_client,
                 &log,
             )
-            .await?;
-
-        self.publish_video_reel(
-            access_token,
-            page_fbid,
-            &video_id,
-            description,
-            long_client,
-            &log,
-        )
-        .await
+            .await
+        }
     }
 
-    /// Upload a video reel and check its status (upload, copyright, thumbnail), returning the video_id without publishing.
-    /// This allows you to handle the publish step separately using `publish_video_reel`.
-    pub async fn upload_video_reel(
-        &self,
-        access_token: &str,
-        page_fbid: &str,
-        file_url: &str,
-        thumb: Option<rusoto_core::ByteStream>,
-        long_client: reqwest::Client,
-        log: impl Fn(LogParams),
-    ) -> Result<String, FbapiError> {
-        // １．アップロード用のURLを取得して動画をアップロードする。
-        let path = self.make_path(&format!("{}/video_reels", page_fbid));
-        let params = vec![("access_token", access_token), ("upload_phase", "start")];
-        let log_params = LogParams::new(&path, &params);
-        let res_request: serde_json::Value = execute_retry(
-            0,
-            || async {
-                long_client
-                    .post(&path)
-                    .form(&params)
-                    .send()
-                    .await
-                    .map_err(|e| e.into())
-            },
-            &log,
-            log_params,
-        )
-        .await?;
+    instrument! {
+        api = "upload_video_reel", node = page_fbid;
+        /// Upload a video reel and check its status (upload, copyright, thumbnail), returning the video_id without publishing.
+        /// This allows you to handle the publish step separately using `publish_video_reel`.
+        pub async fn upload_video_reel(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            file_url: &str,
+            thumb: Option<rusoto_core::ByteStream>,
+            poll: Option<&PollPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<String, FbapiError> {
+            let flow = self.start_flow(flow);
+            // １．アップロード用のURLを取得して動画をアップロードする。
+            let request = GraphRequest::post(self.make_path(&format!("{}/video_reels", page_fbid)))
+                .api("upload_video_reel")
+                .form("upload_phase", "start")
+                .auth(credentials);
+            let res_request = flow
+                .step(
+                    "upload",
+                    self.execute_with(&long_client, &request, Some(&RetryPolicy::no_retry()), &log),
+                )
+                .await?;
 
-        let video_id = res_request["video_id"].as_str();
+            let video_id = res_request["video_id"].as_str();
 
-        // ２．video_urlを使って動画をアップロードする。
-        if let (Some(video_id)) = (video_id) {
-            let upload_reel_url = self.make_video_reel_path(video_id);
-            let log_params = LogParams::new(&upload_reel_url, &vec![("file_url", file_url)]);
-            let upload_response: serde_json::Value = execute_retry(
-                0,
-                || async {
-                    long_client
-                        .post(&upload_reel_url)
-                        .header("Authorization", format!("OAuth {}", access_token))
-                        .header("file_url", file_url)
-                        .send()
-                        .await
-                        .map_err(|e| e.into())
-                },
-                &log,
-                log_params,
-            )
-            .await?;
+            // ２．video_urlを使って動画をアップロードする。
+            if let Some(video_id) = video_id {
+                let upload_request = GraphRequest::post(self.make_video_reel_path(video_id))
+                    .api("upload_video_reel")
+                    .header("file_url", file_url)
+                    .auth_header(credentials);
+                let upload_response = flow
+                    .run(
+                        "upload",
+                        None,
+                        self.execute_with(
+                            &long_client,
+                            &upload_request,
+                            Some(&RetryPolicy::no_retry()),
+                            &log,
+                        ),
+                    )
+                    .await?;
 
-            if upload_response.get("success").and_then(|v| v.as_bool()) != Some(true) {
-                return Err(FbapiError::UnExpected(upload_response.clone()));
-            }
+                if upload_response.get("success").and_then(|v| v.as_bool()) != Some(true) {
+                    return Err(FbapiError::UnExpected(upload_response.clone()));
+                }
 
-            let check_path = self.make_path(&format!(
-                "{}?fields=status&access_token={}",
-                video_id, access_token
-            ));
+                let policy = self.poll_policy_or(poll);
+                wait_reel_uploaded(self, credentials, video_id, &policy, &flow, &log).await?;
 
-            // ３．ステップ２でアップロードした動画のステータスを確認する。
-            loop {
-                let log_params = LogParams::new(&check_path, &vec![]);
-                let status_res: serde_json::Value = execute_retry(
-                    0,
-                    || async {
-                        self.client
-                            .get(&check_path)
-                            .send()
-                            .await
-                            .map_err(|e| e.into())
-                    },
-                    &log,
-                    log_params,
-                )
-                .await?;
-
-                let uploading_status = status_res["status"]["uploading_phase"]["status"].as_str();
+                // ５．サムネイルがある場合はアップロードする。
+                if let Some(bytes) = thumb {
+                    flow.step(
+                        "thumbnail",
+                        self.post_video_thumnail(credentials, video_id, bytes, &log),
+                    )
+                    .await?;
+                };
 
-                match uploading_status {
-                    Some("complete") => break,
-                    Some("in_progress") => {
-                        sleep_sec(1).await;
-                        continue;
-                    }
-                    Some("error") | Some("failed") => {
-                        return Err(FbapiError::UnExpected(json!({
-                            "error": "uploading_phase",
-                            "status": status_res
-                        })));
-                    }
-                    _ => {
-                        if let Some(error_info) =
-                            status_res["status"]["uploading_phase"].get("error")
-                        {
-                            return Err(FbapiError::UnExpected(json!({
-                                "error": "uploading_phase",
-                                "status": status_res
-                            })));
-                        }
-                        return Err(FbapiError::UnExpected(json!({
-                            "error": "uploading_phase",
-                            "status": status_res
-                        })));
-                    }
-                }
+                Ok(video_id.to_string())
+            } else {
+                Err(FbapiError::UnExpected(res_request))
             }
+        }
+    }
 
-            // ４．ステップ２でアップロードした動画の著作権を確認する。
-            loop {
-                let log_params = LogParams::new(&check_path, &vec![]);
-                let status_res: serde_json::Value = execute_retry(
-                    0,
-                    || async {
-                        self.client
-                            .get(&check_path)
-                            .send()
-                            .await
-                            .map_err(|e| e.into())
-                    },
-                    &log,
-                    log_params,
+    instrument! {
+        api = "publish_video_reel", node = page_fbid;
+        /// Publish a video reel using video_id.
+        /// This allows you to handle errors during publishing while retaining the video_id.
+        pub async fn publish_video_reel(
+            &self,
+            credentials: &Credentials,
+            page_fbid: &str,
+            video_id: &str,
+            description: &str,
+            poll: Option<&PollPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<ReelPublished, FbapiError> {
+            let flow = self.start_flow(flow);
+            // ６．動画リールを公開する。
+            let finish_request =
+                GraphRequest::post(self.make_path(&format!("{}/video_reels", page_fbid)))
+                    .api("publish_video_reel")
+                    .form("video_id", video_id)
+                    .form("upload_phase", "finish")
+                    .form("video_state", "PUBLISHED")
+                    .form("description", description)
+                    .auth(credentials);
+            let (finish_res, finish_usage) = flow
+                .step(
+                    "publish",
+                    self.execute_with_usage(
+                        &long_client,
+                        &finish_request,
+                        Some(&RetryPolicy::no_retry()),
+                        &log,
+                    ),
                 )
                 .await?;
 
-                let copyright_status =
-                    status_res["status"]["copyright_check_status"]["status"].as_str();
-
-                match copyright_status {
-                    Some("complete") => {
-                        let matches_found = status_res["status"]["copyright_check_status"]
-                            ["matches_found"]
-                            .as_bool();
-                        if matches_found == Some(true) {
-                            return Err(FbapiError::CopyRight);
-                        }
-                        break;
-                    }
-                    Some("in_progress") => {
-                        sleep_sec(2).await;
-                        continue;
-                    }
-                    Some("error") | Some("failed") => {
-                        return Err(FbapiError::UnExpected(json!({
-                            "error": "copyright_check_status",
-                            "status": status_res
-                        })));
-                    }
-                    _ => {
-                        return Err(FbapiError::UnExpected(json!({
-                            "error": "copyright_check_status",
-                            "status": status_res
-                        })))
-                    }
-                }
+            if finish_res["success"].as_bool() != Some(true) {
+                return Err(FbapiError::UnExpected(finish_res));
             }
 
-            // ５．サムネイルがある場合はアップロードする。
-            match thumb {
-                Some(bytes) => {
-                    self.post_video_thumnail(access_token, &video_id, bytes, &log)
-                        .await?;
+            // post_id が返却されることを確認する。
+            let post_id = finish_res["post_id"].as_str();
+            match post_id {
+                Some(_) => {
+                    let policy = self.poll_policy_or(poll);
+                    wait_reel_published(self, credentials, video_id, &policy, &flow, &log).await?;
+                    let mut published: ReelPublished = parse_raw(finish_res, finish_usage)?;
+                    published.video_id = video_id.to_owned();
+                    Ok(published)
                 }
-                None => {}
-            };
-
-            Ok(video_id.to_string())
-        } else {
-            Err(FbapiError::UnExpected(res_request))
+                None => Err(FbapiError::UnExpected(finish_res)),
+            }
         }
     }
+}
 
-    /// Publish a video reel using video_id.
-    /// This allows you to handle errors during publishing while retaining the video_id.
-    pub async fn publish_video_reel(
-        &self,
-        access_token: &str,
-        page_fbid: &str,
-        video_id: &str,
-        description: &str,
-        long_client: reqwest::Client,
-        log: impl Fn(LogParams),
-    ) -> Result<Value, FbapiError> {
-        let check_path = self.make_path(&format!(
-            "{}?fields=status&access_token={}",
-            video_id, access_token
-        ));
+/// Status request of the reel `video_id`.
+pub(crate) fn check_request(
+    api: &Fbapi,
+    credentials: &Credentials,
+    video_id: &str,
+) -> GraphRequest {
+    GraphRequest::get(api.make_path(video_id))
+        .api("check_video_reel")
+        .query("fields", "status")
+        .auth(credentials)
+}
 
-        // ６．動画リールを公開する。
-        let finish_path = self.make_path(&format!("{}/video_reels", page_fbid));
-        let finish_params = vec![
-            ("access_token", access_token),
-            ("video_id", video_id),
-            ("upload_phase", "finish"),
-            ("video_state", "PUBLISHED"),
-            ("description", description),
-        ];
-        let log_params = LogParams::new(&finish_path, &finish_params);
-        let finish_res: serde_json::Value = execute_retry(
-            0,
-            || async {
-                long_client
-                    .post(&finish_path)
-                    .form(&finish_params)
-                    .send()
-                    .await
-                    .map_err(|e| e.into())
-            },
-            &log,
-            log_params,
-        )
-        .await?;
+/// Wait until the uploaded reel passed the upload and copyright checks.
+pub(crate) async fn wait_reel_uploaded(
+    api: &Fbapi,
+    credentials: &Credentials,
+    video_id: &str,
+    policy: &PollPolicy,
+    flow: &Flow,
+    log: &impl Fn(LogEvent),
+) -> Result<(), FbapiError> {
+    let check_request = check_request(api, credentials, video_id);
 
-        if finish_res["success"].as_bool() != Some(true) {
-            return Err(FbapiError::UnExpected(finish_res));
-        }
+    // ３．ステップ２でアップロードした動画のステータスを確認する。
+    poll_reel(
+        api,
+        &check_request,
+        "reel_uploading",
+        policy,
+        flow,
+        uploading_state,
+        log,
+    )
+    .await?;
 
-        // post_id が返却されることを確認する。
-        let post_id = finish_res["post_id"].as_str();
-        match post_id {
-            Some(id) => {
-                // ７．processing_phase を確認する前に、APIの遅延対策として3秒待機
-                // Facebook APIの遅延が発生する場合があるため、3秒待機する
-                sleep_sec(3).await;
+    // ４．ステップ２でアップロードした動画の著作権を確認する。
+    poll_reel(
+        api,
+        &check_request,
+        "reel_copyright_check",
+        policy,
+        flow,
+        copyright_state,
+        log,
+    )
+    .await?;
+    Ok(())
+}
 
-                let mut retry_count_video_delay = 0;
-                let mut max_retry_video_delay = 3;
-                //アップロードフェーズは呼び出されたものの、Fb API が不安定になり、published が実行されず、status にエラーも返されない可能性があります。
-                //そのため、API のステータス更新を待機する時間は 2 分となります。
-                let mut timeout_upload_phase_not_started = 0;
-                let mut max_timeout_upload_phase_not_started = 120;
-                loop {
-                    let log_params = LogParams::new(&check_path, &vec![]);
-                    let status_res: serde_json::Value = execute_retry(
-                        0,
-                        || async {
-                            self.client
-                                .get(&check_path)
-                                .send()
-                                .await
-                                .map_err(|e| e.into())
-                        },
-                        &log,
-                        log_params,
-                    )
-                    .await?;
+/// Wait until a reel whose finish request was accepted is processed and published;
+/// returns the last status response.
+pub(crate) async fn wait_reel_published(
+    api: &Fbapi,
+    credentials: &Credentials,
+    video_id: &str,
+    policy: &PollPolicy,
+    flow: &Flow,
+    log: &impl Fn(LogEvent),
+) -> Result<serde_json::Value, FbapiError> {
+    let check_request = check_request(api, credentials, video_id);
+
+    // ７．processing_phase を確認する前に、APIの遅延対策として3秒待機
+    // Facebook APIの遅延が発生する場合があるため、3秒待機する
+    flow.sleep(Duration::from_secs(3), "reel_processing", None)
+        .await?;
 
-                    if let Some(error_obj) = status_res.get("error") {
-                        let code = error_obj.get("code").and_then(|v| v.as_u64());
-                        let subcode = error_obj.get("error_subcode").and_then(|v| v.as_u64());
-                        if code == Some(100) && subcode == Some(33) {
-                            if retry_count_video_delay < max_retry_video_delay {
-                                retry_count_video_delay += 1;
-                                sleep_sec(3).await;
-                                continue;
-                            } else {
-                                return Err(FbapiError::VideoDelayed);
-                            }
-                        }
+    //アップロードフェーズは呼び出されたものの、Fb API が不安定になり、published が実行されず、status にエラーも返されない可能性があります。
+    //そのため、API のステータス更新を待機する時間は 2 分となります。
+    let max_timeout_upload_phase_not_started = Duration::from_secs(120);
+    poll_reel(
+        api,
+        &check_request,
+        "reel_processing",
+        policy,
+        flow,
+        |status_res, elapsed| {
+            let processing_status = status_res["status"]["processing_phase"]["status"]
+                .as_str()
+                .unwrap_or_default()
+                .to_owned();
+            let publishing_status = status_res["status"]["publishing_phase"]["status"].as_str();
+            let state = match processing_status.as_str() {
+                "not_started" if publishing_status == Some("not_started") => {
+                    if elapsed < max_timeout_upload_phase_not_started {
+                        PollState::Pending
+                    } else {
+                        PollState::Failed(FbapiError::UploadReelNotStarted)
                     }
+                }
+                "complete" => PollState::Ready,
+                "in_progress" | "not_started"
+                    if !status_res["status"]["processing_phase"]["error"].is_object() =>
+                {
+                    PollState::Pending
+                }
+                _ => PollState::Failed(phase_error("processing_phase", status_res)),
+            };
+            (processing_status, state)
+        },
+        log,
+    )
+    .await?;
 
-                    let processing_status =
-                        status_res["status"]["processing_phase"]["status"].as_str();
-                    let publishing_status =
-                        status_res["status"]["publishing_phase"]["status"].as_str();
+    // ８．publishing_phase を確認する。
+    poll_reel(
+        api,
+        &check_request,
+        "reel_publishing",
+        policy,
+        flow,
+        publishing_state,
+        log,
+    )
+    .await
+}
 
-                    if Some("not_started") == processing_status
-                        && Some("not_started") == publishing_status
-                    {
-                        if timeout_upload_phase_not_started < max_timeout_upload_phase_not_started {
-                            sleep_sec(2).await;
-                            timeout_upload_phase_not_started += 2;
-                            continue;
-                        } else {
-                            return Err(FbapiError::UploadReelNotStarted);
-                        }
-                    }
+async fn poll_reel(
+    api: &Fbapi,
+    request: &GraphRequest,
+    phase: &'static str,
+    policy: &PollPolicy,
+    flow: &Flow,
+    extract: impl FnMut(&serde_json::Value, Duration) -> (String, PollState),
+    log: &impl Fn(LogEvent),
+) -> Result<serde_json::Value, FbapiError> {
+    Poller::new(api, request, phase, policy.clone(), flow)
+        .percent(processing_progress)
+        .run(
+            extract,
+            |tick| api.log_poll_status(request, tick.phase, tick.status, log),
+            log,
+        )
+        .await
+}
 
-                    match processing_status {
-                        Some("complete") => break,
-                        Some("in_progress") | Some("not_started") => {
-                            if status_res["status"]["processing_phase"]["error"].is_object() {
-                                return Err(FbapiError::UnExpected(json!({
-                                    "error": "processing_phase",
-                                    "status": status_res
-                                })));
-                            }
-                            sleep_sec(2).await;
-                            continue;
-                        }
-                        Some("error") | Some("failed") => {
-                            return Err(FbapiError::UnExpected(json!({
-                                "error": "processing_phase",
-                                "status": status_res
-                            })));
-                        }
-                        _ => {
-                            return Err(FbapiError::UnExpected(json!({
-                                "error": "processing_phase",
-                                "status": status_res
-                            })));
-                        }
-                    }
-                }
+fn uploading_state(status_res: &serde_json::Value, _: Duration) -> (String, PollState) {
+    let status = status_res["status"]["uploading_phase"]["status"]
+        .as_str()
+        .unwrap_or_default();
+    let state = match status {
+        "complete" => PollState::Ready,
+        "in_progress" => PollState::Pending,
+        _ => PollState::Failed(phase_error("uploading_phase", status_res)),
+    };
+    (status.to_owned(), state)
+}
 
-                // ８．publishing_phase を確認する。
-                loop {
-                    let log_params = LogParams::new(&check_path, &vec![]);
-                    let status_res: serde_json::Value = execute_retry(
-                        0,
-                        || async {
-                            self.client
-                                .get(&check_path)
-                                .send()
-                                .await
-                                .map_err(|e| e.into())
-                        },
-                        &log,
-                        log_params,
-                    )
-                    .await?;
+fn copyright_state(status_res: &serde_json::Value, _: Duration) -> (String, PollState) {
+    let copyright_check = &status_res["status"]["copyright_check_status"];
+    let status = copyright_check["status"].as_str().unwrap_or_default();
+    // 著作権の一致は complete として報告しない（PublishHandle が Ready に進まないように）。
+    match status {
+        "complete" if copyright_check["matches_found"].as_bool() == Some(true) => (
+            "matches_found".to_owned(),
+            PollState::Failed(FbapiError::CopyRight),
+        ),
+        "complete" => (status.to_owned(), PollState::Ready),
+        "in_progress" => (status.to_owned(), PollState::Pending),
+        _ => (
+            status.to_owned(),
+            PollState::Failed(phase_error("copyright_check_status", status_res)),
+        ),
+    }
+}
 
-                    let publishing_status =
-                        status_res["status"]["publishing_phase"]["status"].as_str();
+fn publishing_state(status_res: &serde_json::Value, _: Duration) -> (String, PollState) {
+    let status = status_res["status"]["publishing_phase"]["status"]
+        .as_str()
+        .unwrap_or_default();
+    let state = match status {
+        "complete" => PollState::Ready,
+        "in_progress" | "not_started" => PollState::Pending,
+        _ => PollState::Failed(phase_error("publishing_phase", status_res)),
+    };
+    (status.to_owned(), state)
+}
 
-                    match publishing_status {
-                        Some("complete") => break,
-                        Some("in_progress") | Some("not_started") => {
-                            sleep_sec(2).await;
-                            continue;
-                        }
-                        Some("error") | Some("failed") => {
-                            return Err(FbapiError::UnExpected(json!({
-                                "error": "publishing_phase",
-                                "status": status_res
-                            })));
-                        }
-                        _ => {
-                            return Err(FbapiError::UnExpected(json!({
-                                "error": "publishing_phase",
-                                "status": status_res
-                            })));
-                        }
-                    }
-                }
-                return Ok(finish_res);
-            }
-            None => return Err(FbapiError::UnExpected(finish_res)),
-        }
+fn phase_error(phase: &str, status_res: &serde_json::Value) -> FbapiError {
+    FbapiError::UnExpected(json!({
+        "error": phase,
+        "status": status_res
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_copyright_match_is_not_ready() {
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new().rule(
+                    FaultRule::new(Fault::Response {
+                        status: 200,
+                        body: json!({
+                            "status": {
+                                "uploading_phase": {"status": "complete"},
+                                "copyright_check_status": {
+                                    "status": "complete",
+                                    "matches_found": true,
+                                },
+                            },
+                        })
+                        .to_string(),
+                    })
+                    .api("check_video_reel"),
+                ),
+            )
+            .build()
+            .unwrap();
+        let handle = Arc::new(Mutex::new(PublishHandle::facebook_reel(
+            "123", "456", "hello",
+        )));
+        let recorded = handle.clone();
+        let flow = api
+            .start_flow(Some(&FlowOptions::default().progress(move |progress| {
+                recorded.lock().unwrap().record(&progress)
+            })));
+        let result = wait_reel_uploaded(
+            &api,
+            &Credentials::new("EAAtoken"),
+            "456",
+            &PollPolicy::fixed(1, Duration::from_millis(1)),
+            &flow,
+            &|_| {},
+        )
+        .await;
+        assert!(matches!(result, Err(FbapiError::CopyRight)));
+        assert_eq!(handle.lock().unwrap().phase, PublishPhase::Processing);
     }
 }
@@ -2,21 +2,24 @@ use crate::*;
 use reqwest::multipart::Form;
 
 impl Fbapi {
-    pub async fn post_video_thumnail(
-        &self,
-        access_token: &str,
-        video_id: &str,
-        bytes: rusoto_core::ByteStream,
-        log: impl Fn(LogParams),
-    ) -> Result<serde_json::Value, FbapiError> {
-        let path = self.make_path(&format!("{}/thumbnails", video_id));
-        let params = vec![("access_token", access_token), ("video_id", video_id)];
-        let log_params = LogParams::new(&path, &params);
-        let part = make_part("thumnail", bytes)?;
-        let form = Form::new()
-            .text("access_token", access_token.to_string())
-            .text("is_preferred", "true")
-            .part("source", part);
-        execute_form(&self.client, &path, form, &log, log_params).await
+    instrument! {
+        api = "post_video_thumnail", node = video_id;
+        pub async fn post_video_thumnail(
+            &self,
+            credentials: &Credentials,
+            video_id: &str,
+            bytes: rusoto_core::ByteStream,
+            log: impl Fn(LogEvent),
+        ) -> Result<serde_json::Value, FbapiError> {
+            let request = GraphRequest::post(self.make_path(&format!("{}/thumbnails", video_id)))
+                .api("post_video_thumnail")
+                .form("is_preferred", "true")
+                .auth(credentials);
+            let bytes = read_bytes(bytes).await?;
+            let form = || Ok(Form::new().part("source", make_part("thumnail", &bytes)?));
+            self.execute_form(&request, form, &log)
+                .await
+                .map(|(json, _)| json)
+        }
     }
 }
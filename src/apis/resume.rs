@@ -0,0 +1,310 @@
+use crate::apis::check_ig_media::check_ig_media_loop;
+use crate::apis::post_ig_media_publish::{container_published_check, is_container_published};
+use crate::apis::post_video::{check_loop, is_video_published, video_published_check};
+use crate::apis::post_video_reel::{check_request, wait_reel_published, wait_reel_uploaded};
+use crate::responses::{PublishOutcome, PublishedMedia};
+use crate::*;
+
+impl Fbapi {
+    instrument! {
+        api = "resume", node = handle.target.id();
+        /// Continue a publish from the phase stored in `handle`.
+        ///
+        /// From `Processing` the status is polled again with `poll`, or the client [`PollPolicy`],
+        /// before publishing, from `Ready` the media is published. From `Publishing` the earlier
+        /// request may have gone through, so the media is read back first and reported as
+        /// `PublishOutcome::AlreadyPublished` when it is published. All waits on a reel use `poll`
+        /// as well. A reel whose publishing phase has started is polled until it completes; one
+        /// still `not_started` is finished again. A `Published` handle returns `AlreadyPublished`
+        /// without any request. The thumbnail step of the upload is not repeated. A handle whose
+        /// chunked upload is still in progress fails, since the video is needed again; continue it
+        /// with `resume_video_file`.
+        pub async fn resume(
+            &self,
+            credentials: &Credentials,
+            handle: &PublishHandle,
+            poll: Option<&PollPolicy>,
+            retry: Option<&RetryPolicy>,
+            flow: Option<&FlowOptions>,
+            long_client: reqwest::Client,
+            log: impl Fn(LogEvent),
+        ) -> Result<PublishOutcome<PublishedMedia>, FbapiError> {
+            let flow = self.start_flow(flow);
+            let policy = self.poll_policy_or(poll);
+            if handle.phase == PublishPhase::Published {
+                return Ok(PublishOutcome::AlreadyPublished(
+                    json!({ "id": handle.target.id() }),
+                ));
+            }
+            if let Some(upload) = &handle.upload {
+                return Err(FbapiError::UnExpected(json!({
+                    "error": "chunked upload not finished, resume it with resume_video_file",
+                    "upload_session_id": upload.upload_session_id,
+                    "start_offset": upload.start_offset,
+                })));
+            }
+
+            match &handle.target {
+                PublishTarget::FacebookVideo {
+                    video_id,
+                    via_videos_tab,
+                    ..
+                } => {
+                    match handle.phase {
+                        PublishPhase::Processing => {
+                            check_loop(
+                                self,
+                                credentials,
+                                video_id,
+                                retry,
+                                policy.clone(),
+                                &flow,
+                                &log,
+                            )
+                            .await?
+                        }
+                        PublishPhase::Publishing => {
+                            let check =
+                                video_published_check(&self.make_path(video_id), credentials);
+                            let res = flow
+                                .run("publish", None, self.execute(&check, retry, &log))
+                                .await?;
+                            if is_video_published(&res) {
+                                return Ok(PublishOutcome::AlreadyPublished(res));
+                            }
+                        }
+                        _ => {}
+                    }
+                    let outcome = flow
+                        .step(
+                            "publish",
+                            self.publish_video(credentials, video_id, *via_videos_tab, retry, &log),
+                        )
+                        .await?;
+                    Ok(outcome.map(PublishedMedia::Video))
+                }
+                PublishTarget::FacebookReel {
+                    page_id,
+                    video_id,
+                    description,
+                } => {
+                    match handle.phase {
+                        PublishPhase::Processing => {
+                            wait_reel_uploaded(self, credentials, video_id, &policy, &flow, &log)
+                                .await?
+                        }
+                        PublishPhase::Publishing => {
+                            // 公開リクエストが届いていない（publishing_phase が not_started）場合
+                            // のみ再送し、それ以外は公開が終わるまで状態を確認する。
+                            let status = flow
+                                .run(
+                                    "publish",
+                                    None,
+                                    self.execute(
+                                        &check_request(self, credentials, video_id),
+                                        retry,
+                                        &log,
+                                    ),
+                                )
+                                .await?;
+                            if status["status"]["publishing_phase"]["status"] != "not_started" {
+                                let res = wait_reel_published(
+                                    self,
+                                    credentials,
+                                    video_id,
+                                    &policy,
+                                    &flow,
+                                    &log,
+                                )
+                                .await?;
+                                return Ok(PublishOutcome::AlreadyPublished(res));
+                            }
+                        }
+                        _ => {}
+                    }
+                    let published = self
+                        .publish_video_reel(
+                            credentials,
+                            page_id,
+                            video_id,
+                            description,
+                            Some(&policy),
+                            Some(&flow.options()),
+                            long_client,
+                            &log,
+                        )
+                        .await?;
+                    Ok(PublishOutcome::Published(PublishedMedia::Reel(published)))
+                }
+                PublishTarget::Instagram {
+                    account_id,
+                    creation_id,
+                } => {
+                    match handle.phase {
+                        PublishPhase::Processing => {
+                            check_ig_media_loop(
+                                self,
+                                credentials,
+                                creation_id,
+                                policy.clone(),
+                                retry,
+                                &flow,
+                                &log,
+                            )
+                            .await?
+                        }
+                        PublishPhase::Publishing => {
+                            let check = container_published_check(
+                                &self.make_path(creation_id),
+                                credentials,
+                            );
+                            let res = flow
+                                .run("publish", None, self.execute(&check, retry, &log))
+                                .await?;
+                            if is_container_published(&res) {
+                                return Ok(PublishOutcome::AlreadyPublished(res));
+                            }
+                        }
+                        _ => {}
+                    }
+                    let outcome = flow
+                        .step(
+                            "publish",
+                            self.post_ig_media_publish(
+                                credentials,
+                                account_id,
+                                creation_id,
+                                retry,
+                                &log,
+                            ),
+                        )
+                        .await?;
+                    Ok(outcome.map(PublishedMedia::Instagram))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resume_publishing_instagram() {
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new().rule(
+                    FaultRule::new(Fault::Response {
+                        status: 200,
+                        body: r#"{"status_code":"PUBLISHED","id":"18000"}"#.to_owned(),
+                    })
+                    .api("check_ig_media_published"),
+                ),
+            )
+            .build()
+            .unwrap();
+        let mut handle = PublishHandle::instagram("17841", "18000");
+        handle.set_phase(PublishPhase::Publishing);
+        let outcome = api
+            .resume(
+                &Credentials::new("EAAtoken"),
+                &handle,
+                None,
+                None,
+                None,
+                reqwest::Client::new(),
+                |_| {},
+            )
+            .await
+            .unwrap();
+        match outcome {
+            PublishOutcome::AlreadyPublished(res) => assert_eq!(res["status_code"], "PUBLISHED"),
+            other => panic!("Expected AlreadyPublished: {:?}", other),
+        }
+
+        handle.set_phase(PublishPhase::Published);
+        let outcome = api
+            .resume(
+                &Credentials::new("EAAtoken"),
+                &handle,
+                None,
+                None,
+                None,
+                reqwest::Client::new(),
+                |_| {},
+            )
+            .await
+            .unwrap();
+        assert!(outcome.is_already_published());
+    }
+
+    #[tokio::test]
+    async fn test_resume_processing_with_poll() {
+        let api = Fbapi::builder("v19.0").build().unwrap();
+        let handle = PublishHandle::facebook_video("123", "456", false);
+        let result = api
+            .resume(
+                &Credentials::new("EAAtoken"),
+                &handle,
+                Some(&PollPolicy::fixed(0, Duration::from_millis(1))),
+                None,
+                None,
+                reqwest::Client::new(),
+                |_| {},
+            )
+            .await;
+        assert!(matches!(result, Err(FbapiError::VideoTimeout)));
+    }
+
+    #[tokio::test]
+    async fn test_resume_publishing_reel_not_started() {
+        let status = |phase: &str| Fault::Response {
+            status: 200,
+            body: format!(
+                r#"{{"status":{{"processing_phase":{{"status":"{0}"}},"publishing_phase":{{"status":"{0}"}}}}}}"#,
+                phase
+            ),
+        };
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(
+                        FaultRule::new(status("not_started"))
+                            .api("check_video_reel")
+                            .first_calls(1),
+                    )
+                    .rule(FaultRule::new(status("complete")).api("check_video_reel"))
+                    .rule(
+                        FaultRule::new(Fault::Response {
+                            status: 200,
+                            body: r#"{"success":true,"post_id":"789"}"#.to_owned(),
+                        })
+                        .api("publish_video_reel"),
+                    ),
+            )
+            .build()
+            .unwrap();
+        let mut handle = PublishHandle::facebook_reel("123", "456", "hello");
+        handle.set_phase(PublishPhase::Publishing);
+        let outcome = api
+            .resume(
+                &Credentials::new("EAAtoken"),
+                &handle,
+                None,
+                None,
+                None,
+                reqwest::Client::new(),
+                |_| {},
+            )
+            .await
+            .unwrap();
+        match outcome {
+            PublishOutcome::Published(PublishedMedia::Reel(reel)) => {
+                assert_eq!(reel.post_id, "789");
+                assert_eq!(reel.video_id, "456");
+            }
+            other => panic!("Expected Published: {:?}", other),
+        }
+    }
+}
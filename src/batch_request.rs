@@ -8,15 +8,15 @@ pub struct BatchRequest {
     pub batch_count: usize,
 }
 
-impl Into<serde_json::Value> for BatchRequest {
-    fn into(self) -> serde_json::Value {
-        self.inner
+impl From<BatchRequest> for serde_json::Value {
+    fn from(val: BatchRequest) -> Self {
+        val.inner
     }
 }
 
-impl ToString for BatchRequest {
-    fn to_string(&self) -> String {
-        self.inner.to_string()
+impl std::fmt::Display for BatchRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
     }
 }
 
@@ -24,6 +24,12 @@ pub struct Builder {
     items: Vec<Item>,
 }
 
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Builder {
     pub fn new() -> Self {
         Self { items: Vec::new() }
@@ -107,7 +113,7 @@ impl Builder {
         let batch_count = self.items.len();
         Ok(BatchRequest {
             inner: serde_json::to_value(self.items)?,
-            batch_count: batch_count,
+            batch_count,
         })
     }
 }
@@ -156,11 +162,9 @@ impl ItemCommon {
         if params.is_empty() {
             relative_url.into_owned()
         } else {
-            let query = params
-                .into_iter()
-                .map(|&(key, value)| format!("{}={}", key, value))
-                .collect::<Vec<_>>()
-                .join("&");
+            let query = url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(params)
+                .finish();
             format!("{}?{}", &relative_url, query)
         }
     }
@@ -240,6 +244,19 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_item_params_are_encoded() -> Result<(), FbapiError> {
+        let batch = Builder::new()
+            .get("me/feed", &[("fields", "comments.limit(1){message}")])
+            .build()?;
+        let json: serde_json::Value = batch.into();
+        assert_eq!(
+            json[0]["relative_url"],
+            "me/feed?fields=comments.limit%281%29%7Bmessage%7D"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_item_with_body() -> Result<(), serde_json::Error> {
         let item = Item::Post {
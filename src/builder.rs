@@ -0,0 +1,273 @@
+use crate::*;
+use std::sync::Arc;
+
+/// Builder for [`Fbapi`].
+///
+/// ```no_run
+/// # use fbapi_reqwest::*;
+/// # use std::time::Duration;
+/// let api = Fbapi::builder("v19.0")
+///     .graph_url("http://127.0.0.1:8080/")
+///     .user_agent("my-app/1.0")
+///     .timeout(Duration::from_secs(30))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct FbapiBuilder {
+    version: String,
+    graph_url: String,
+    graph_video_url: String,
+    rupload_url: String,
+    timeout: Duration,
+    user_agent: Option<String>,
+    proxy: Option<reqwest::Proxy>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    fault_injection: Option<FaultInjection>,
+    retry_policy: RetryPolicy,
+    retry_classifier: Arc<dyn RetryClassifier>,
+    poll_policy: PollPolicy,
+    flow_deadline: Duration,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    auth_mode: AuthMode,
+    redaction: Redaction,
+    usage_registry: UsageRegistry,
+    app_id: Option<String>,
+    throttle_policy: Option<ThrottlePolicy>,
+}
+
+impl FbapiBuilder {
+    pub fn new(version: &str) -> Self {
+        Self {
+            version: version.to_owned(),
+            graph_url: GRAPH_PREFIX.to_owned(),
+            graph_video_url: VIDEO_PREFIX.to_owned(),
+            rupload_url: VIDEO_REEL_URL_PREFIX.to_owned(),
+            timeout: Duration::from_secs(30),
+            user_agent: None,
+            proxy: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            fault_injection: None,
+            retry_policy: RetryPolicy::default(),
+            retry_classifier: Arc::new(DefaultRetryClassifier),
+            poll_policy: PollPolicy::default(),
+            flow_deadline: Duration::from_secs(30 * 60),
+            token_provider: None,
+            auth_mode: AuthMode::Header,
+            redaction: Redaction::default(),
+            usage_registry: UsageRegistry::default(),
+            app_id: None,
+            throttle_policy: None,
+        }
+    }
+
+    /// Base URL of the Graph API (default `https://graph.facebook.com/`).
+    pub fn graph_url(mut self, url: &str) -> Self {
+        self.graph_url = normalize_base_url(url);
+        self
+    }
+
+    /// Base URL of the Graph API for video uploads (default `https://graph-video.facebook.com/`).
+    pub fn graph_video_url(mut self, url: &str) -> Self {
+        self.graph_video_url = normalize_base_url(url);
+        self
+    }
+
+    /// Base URL of the resumable upload API for reels (default `https://rupload.facebook.com/video-upload/`).
+    pub fn rupload_url(mut self, url: &str) -> Self {
+        self.rupload_url = normalize_base_url(url);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_owned());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Shorthand for [`FaultInjection::rate_limit_emulation`]; `false` removes any fault injection.
+    pub fn rate_limit_emulation(mut self, rate_limit_emulation: bool) -> Self {
+        self.fault_injection = if rate_limit_emulation {
+            Some(FaultInjection::rate_limit_emulation())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Faults to inject instead of real responses, for testing (default: none).
+    pub fn fault_injection(mut self, fault_injection: FaultInjection) -> Self {
+        self.fault_injection = Some(fault_injection);
+        self
+    }
+
+    /// Retry policy of every call that is not given its own (default [`RetryPolicy::default`]).
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Which failures are retried (default [`DefaultRetryClassifier`]).
+    pub fn retry_classifier(mut self, retry_classifier: Arc<dyn RetryClassifier>) -> Self {
+        self.retry_classifier = retry_classifier;
+        self
+    }
+
+    /// Polling of the upload flows, unless a call gives its own (default
+    /// [`PollPolicy::default`]).
+    pub fn poll_policy(mut self, poll_policy: PollPolicy) -> Self {
+        self.poll_policy = poll_policy;
+        self
+    }
+
+    /// Overall deadline of a flow called without [`FlowOptions::deadline`] (default 30 minutes).
+    pub fn flow_deadline(mut self, flow_deadline: Duration) -> Self {
+        self.flow_deadline = flow_deadline;
+        self
+    }
+
+    /// Token source used by [`Fbapi::credentials_for`] and to refresh expired tokens.
+    pub fn token_provider(mut self, token_provider: Arc<dyn TokenProvider>) -> Self {
+        self.token_provider = Some(token_provider);
+        self
+    }
+
+    /// Where the access token is sent (default `AuthMode::Header`).
+    /// `AuthMode::Parameter` restores the `access_token` parameter for every request;
+    /// use [`Credentials::with_auth_mode`] to switch only some calls.
+    pub fn auth_mode(mut self, auth_mode: AuthMode) -> Self {
+        self.auth_mode = auth_mode;
+        self
+    }
+
+    /// Masking of tokens and secrets in `LogEvent`s (default [`Redaction::default`]).
+    /// Use [`Redaction::disabled`] to log everything while debugging locally.
+    pub fn redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    /// Registry updated with the usage headers of every response (default: a new one).
+    /// Pass the same registry to clients that share an app or pages.
+    pub fn usage_registry(mut self, usage_registry: UsageRegistry) -> Self {
+        self.usage_registry = usage_registry;
+        self
+    }
+
+    /// App the client's tokens belong to. Its app usage is kept under this id in the
+    /// [`UsageRegistry`], so clients of several apps can share one registry. Clients
+    /// without an app id share the usage kept under an empty id.
+    pub fn app_id(mut self, app_id: &str) -> Self {
+        self.app_id = Some(app_id.to_owned());
+        self
+    }
+
+    /// Delay or reject requests when the usage reported by Facebook gets close to the
+    /// rate limits (default: no throttling).
+    pub fn throttle_policy(mut self, throttle_policy: ThrottlePolicy) -> Self {
+        self.throttle_policy = Some(throttle_policy);
+        self
+    }
+
+    /// Build a client with the same user agent, proxy and pool settings but another timeout.
+    /// Useful for the `long_client` argument of the video APIs.
+    pub fn build_client(&self, timeout: Duration) -> Result<reqwest::Client, FbapiError> {
+        let mut builder = reqwest::ClientBuilder::new().timeout(timeout);
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        builder.build().map_err(|e| e.into())
+    }
+
+    pub fn build(self) -> Result<Fbapi, FbapiError> {
+        Ok(Fbapi {
+            client: self.build_client(self.timeout)?,
+            version: self.version,
+            graph_url: self.graph_url,
+            graph_video_url: self.graph_video_url,
+            rupload_url: self.rupload_url,
+            fault_injection: self.fault_injection,
+            retry_policy: self.retry_policy,
+            retry_classifier: self.retry_classifier,
+            poll_policy: self.poll_policy,
+            flow_deadline: self.flow_deadline,
+            token_provider: self.token_provider,
+            auth_mode: self.auth_mode,
+            redaction: self.redaction,
+            usage_registry: self.usage_registry,
+            app_id: self.app_id.unwrap_or_default(),
+            throttle_policy: self.throttle_policy,
+        })
+    }
+}
+
+fn normalize_base_url(url: &str) -> String {
+    if url.ends_with('/') {
+        url.to_owned()
+    } else {
+        format!("{}/", url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_urls() {
+        let api = Fbapi::builder("v19.0")
+            .graph_url("http://127.0.0.1:8080")
+            .graph_video_url("http://127.0.0.1:8081/")
+            .rupload_url("http://127.0.0.1:8082/video-upload")
+            .build()
+            .unwrap();
+        assert_eq!(api.make_path("me"), "http://127.0.0.1:8080/v19.0/me");
+        assert_eq!(
+            api.make_video_path("123/videos"),
+            "http://127.0.0.1:8081/v19.0/123/videos"
+        );
+        assert_eq!(
+            api.make_video_reel_path("456"),
+            "http://127.0.0.1:8082/video-upload/v19.0/456"
+        );
+    }
+
+    #[test]
+    fn test_defaults() {
+        let api = Fbapi::new("v19.0", 10, false).unwrap();
+        assert_eq!(api.make_path("me"), "https://graph.facebook.com/v19.0/me");
+        assert_eq!(api.retry_policy(), &RetryPolicy::default());
+        assert_eq!(api.poll_policy(), &PollPolicy::default());
+        assert_eq!(api.flow_deadline(), Duration::from_secs(30 * 60));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::*;
+use std::sync::{Arc, RwLock};
+
+/// Where the access token is sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthMode {
+    /// `Authorization: OAuth {token}` header, which keeps the token out of urls and logs.
+    Header,
+    /// `access_token` parameter in the query string or form body, for endpoints that
+    /// do not accept the header.
+    Parameter,
+}
+
+/// Access token and optional app secret used to authorize a Graph API call.
+///
+/// When an app secret is set, `appsecret_proof` is added to every request made with
+/// these credentials, so the app can enable "Require App Secret".
+///
+/// Clones share the access token: when it is refreshed through a [`TokenProvider`],
+/// every clone sees the new token.
+#[derive(Clone)]
+pub struct Credentials {
+    access_token: Arc<RwLock<String>>,
+    app_secret: Option<String>,
+    subject: Option<String>,
+    auth_mode: Option<AuthMode>,
+}
+
+impl Credentials {
+    pub fn new(access_token: &str) -> Self {
+        Self {
+            access_token: Arc::new(RwLock::new(access_token.to_owned())),
+            app_secret: None,
+            subject: None,
+            auth_mode: None,
+        }
+    }
+
+    pub fn with_app_secret(mut self, app_secret: &str) -> Self {
+        self.app_secret = Some(app_secret.to_owned());
+        self
+    }
+
+    /// Page id or Instagram user id the token belongs to, used to refresh it
+    /// through the [`TokenProvider`].
+    pub fn for_subject(mut self, subject: &str) -> Self {
+        self.subject = Some(subject.to_owned());
+        self
+    }
+
+    /// Send the token this way instead of the client default ([`FbapiBuilder::auth_mode`]).
+    pub fn with_auth_mode(mut self, auth_mode: AuthMode) -> Self {
+        self.auth_mode = Some(auth_mode);
+        self
+    }
+
+    pub fn auth_mode(&self) -> Option<AuthMode> {
+        self.auth_mode
+    }
+
+    pub fn access_token(&self) -> String {
+        self.access_token
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn subject(&self) -> Option<&str> {
+        self.subject.as_deref()
+    }
+
+    pub(crate) fn set_access_token(&self, access_token: &str) {
+        *self.access_token.write().unwrap_or_else(|e| e.into_inner()) = access_token.to_owned();
+    }
+
+    /// HMAC-SHA256 of the access token keyed by the app secret, if an app secret is set.
+    pub fn appsecret_proof(&self) -> Option<String> {
+        self.app_secret
+            .as_ref()
+            .map(|secret| sign(&self.access_token(), secret))
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_token", &"***")
+            .field("app_secret", &self.app_secret.as_ref().map(|_| "***"))
+            .field("subject", &self.subject)
+            .field("auth_mode", &self.auth_mode)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_appsecret_proof() {
+        let credentials = Credentials::new("EAAtoken");
+        assert_eq!(credentials.appsecret_proof(), None);
+        let credentials = credentials.with_app_secret("s3cr3t");
+        assert_eq!(
+            credentials.appsecret_proof(),
+            Some(sign("EAAtoken", "s3cr3t"))
+        );
+        let debug = format!("{:?}", credentials);
+        assert!(!debug.contains("EAAtoken") && !debug.contains("s3cr3t"));
+    }
+}
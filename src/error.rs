@@ -36,6 +36,38 @@ pub enum FbapiError {
     #[error("Facebook upload reel not started after phase published")]
     UploadReelNotStarted,
 
+    #[error("Facebook response does not match at {path}: {source} (response: {response})")]
+    Deserialize {
+        path: String,
+        source: serde_json::Error,
+        response: serde_json::Value,
+    },
+
+    #[error("No token provider registered")]
+    NoTokenProvider,
+
+    #[error("Injected network timeout")]
+    InjectedTimeout,
+
+    #[error("Injected timeout after the request was sent")]
+    InjectedResponseTimeout,
+
+    #[error("Throttled before reaching the Facebook rate limits (retry after {retry_after:?})")]
+    Throttled {
+        retry_after: Option<std::time::Duration>,
+    },
+
+    #[error("Flow stopped by its deadline or cancellation during {phase} (last status: {last_status:?})")]
+    Timeout {
+        phase: String,
+        last_status: Option<String>,
+    },
+
+    /// A publish was found already done when checking before a retry; publishing APIs
+    /// report it as `PublishOutcome::AlreadyPublished`.
+    #[error("Already published: {0}")]
+    AlreadyPublished(serde_json::Value),
+
     #[error("Invalid media ID: {id} (response: {response})")]
     InvalidMediaId {
         id: String,
@@ -44,7 +76,7 @@ pub enum FbapiError {
 }
 
 // ユーザに表示するエラー内容
-const SHOULD_REOAUTH: &'static str =
+const SHOULD_REOAUTH: &str =
     "アカウントの認証エラーで投稿が失敗しました。アカウントを再認証してください。";
 
 // Graph API から返ってくるエラー
@@ -60,6 +92,51 @@ struct FbDetailError {
 }
 
 impl FbapiError {
+    /// Graph API error code when this is a `Facebook` error.
+    pub fn error_code(&self) -> Option<u64> {
+        match self {
+            FbapiError::Facebook(value) => value["error"]["code"].as_u64(),
+            _ => None,
+        }
+    }
+
+    /// Graph API error subcode when this is a `Facebook` error.
+    pub fn error_subcode(&self) -> Option<u64> {
+        match self {
+            FbapiError::Facebook(value) => value["error"]["error_subcode"].as_u64(),
+            _ => None,
+        }
+    }
+
+    /// `fbtrace_id` of a `Facebook` error, to quote when reporting to Meta.
+    pub fn fbtrace_id(&self) -> Option<&str> {
+        match self {
+            FbapiError::Facebook(value) => value["error"]["fbtrace_id"].as_str(),
+            _ => None,
+        }
+    }
+
+    /// True for an invalid or expired access token (code 190).
+    pub fn is_invalid_token(&self) -> bool {
+        self.error_code() == Some(190)
+    }
+
+    /// True when the request never reached Facebook (a connection error or an injected
+    /// timeout), so sending it again cannot publish twice.
+    pub fn is_not_sent(&self) -> bool {
+        match self {
+            FbapiError::InjectedTimeout => true,
+            FbapiError::Reqwest(e) => e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// True for the duplicate post error (code 506) returned when the same content is
+    /// published twice.
+    pub fn is_duplicate(&self) -> bool {
+        self.error_code() == Some(506)
+    }
+
     pub fn make_error_content_for_user(&self) -> String {
         match self {
             FbapiError::Facebook(value) => {
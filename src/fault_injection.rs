@@ -0,0 +1,343 @@
+use crate::*;
+use rand::Rng;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Failure returned instead of (or before) a real Graph API response.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// Graph API error body, answered with HTTP 400. For `post_batch` every item of the
+    /// batch gets the error, as Facebook does for throttled batches.
+    GraphError(serde_json::Value),
+    /// Any response.
+    Response { status: u16, body: String },
+    /// Wait, then fail with `FbapiError::InjectedTimeout` without sending the request.
+    Timeout(Duration),
+    /// Wait, then fail with `FbapiError::InjectedResponseTimeout`, as when Facebook accepted
+    /// the request but the response was lost. Nothing is sent either.
+    ResponseTimeout(Duration),
+    /// HTTP 200 with a truncated json body.
+    MalformedJson,
+    /// Wait, then send the request as usual.
+    Slow(Duration),
+    /// Status checks (`check_video`, `check_video_reel`, `check_ig_media`) answer
+    /// "in progress"; other APIs are not affected. Limit it with `FaultRule::first_calls`
+    /// for a slow status, leave it unlimited for a stuck one.
+    StuckStatus,
+}
+
+impl Fault {
+    /// `(#32) Page request limit reached`, the error of the former `rate_limit_emulation`.
+    pub fn rate_limit() -> Self {
+        Fault::GraphError(ERROR_VALUE.clone())
+    }
+
+    pub fn graph_error(code: u64, error_subcode: Option<u64>, message: &str) -> Self {
+        let mut error = json!({
+            "message": message,
+            "type": "OAuthException",
+            "code": code,
+            "fbtrace_id": "injected",
+        });
+        if let Some(error_subcode) = error_subcode {
+            error["error_subcode"] = error_subcode.into();
+        }
+        Fault::GraphError(json!({ "error": error }))
+    }
+}
+
+/// When a [`Fault`] applies.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trigger {
+    Always,
+    /// Each matching call fails with this probability (0.0 to 1.0). Values outside the
+    /// range are clamped to it and NaN never applies.
+    Probability(f64),
+    /// The first `n` matching calls fail.
+    FirstCalls(usize),
+    /// Only the `n`th matching call fails, counting from 1.
+    NthCall(usize),
+}
+
+/// `probability` within the range accepted by `gen_bool`, NaN counting as never.
+fn clamp_probability(probability: f64) -> f64 {
+    if probability.is_nan() {
+        0.0
+    } else {
+        probability.clamp(0.0, 1.0)
+    }
+}
+
+/// A fault, the APIs it is scoped to and its trigger.
+///
+/// API names are those of the request being sent: the public method for single call
+/// APIs (`get_object`, `post_batch`, ...) and the step for flows (`upload_video`,
+/// `check_video`, `publish_video`, `upload_video_file`, `upload_video_file_chunk`,
+/// `upload_video_reel`, `check_video_reel`, `publish_video_reel`, `post_ig_image_container`,
+/// `check_ig_media`, `post_ig_media_publish`, ...). The reads done before retrying a publish are
+/// `check_ig_media_published`, `check_video_published` and `check_feed_published`.
+#[derive(Clone, Debug)]
+pub struct FaultRule {
+    fault: Fault,
+    apis: Vec<String>,
+    trigger: Trigger,
+    calls: Arc<AtomicUsize>,
+}
+
+impl FaultRule {
+    /// Rule applying `fault` to every call of every API.
+    pub fn new(fault: Fault) -> Self {
+        Self {
+            fault,
+            apis: vec![],
+            trigger: Trigger::Always,
+            calls: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Restrict the rule to `api`; may be called several times.
+    pub fn api(mut self, api: &str) -> Self {
+        self.apis.push(api.to_owned());
+        self
+    }
+
+    /// Fail each matching call with `probability`, clamped to 0.0 to 1.0 (NaN as 0.0).
+    pub fn probability(self, probability: f64) -> Self {
+        self.trigger(Trigger::Probability(clamp_probability(probability)))
+    }
+
+    pub fn first_calls(self, n: usize) -> Self {
+        self.trigger(Trigger::FirstCalls(n))
+    }
+
+    pub fn nth_call(self, n: usize) -> Self {
+        self.trigger(Trigger::NthCall(n))
+    }
+
+    pub fn trigger(mut self, trigger: Trigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    fn applies(&self, api: &str) -> bool {
+        if !self.apis.is_empty() && !self.apis.iter().any(|a| a == api) {
+            return false;
+        }
+        if self.fault == Fault::StuckStatus && status_body(api).is_none() {
+            return false;
+        }
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        match self.trigger {
+            Trigger::Always => true,
+            Trigger::Probability(probability) => {
+                rand::thread_rng().gen_bool(clamp_probability(probability))
+            }
+            Trigger::FirstCalls(n) => call <= n,
+            Trigger::NthCall(n) => call == n,
+        }
+    }
+}
+
+/// Faults injected into the requests of an [`Fbapi`], for testing error handling end to
+/// end. The first rule that applies to a call wins; call counts are shared by clones.
+///
+/// ```
+/// # use fbapi_reqwest::fault_injection::{Fault, FaultInjection, FaultRule};
+/// let faults = FaultInjection::new()
+///     .rule(FaultRule::new(Fault::rate_limit()).api("post_ig_media_publish").probability(0.2))
+///     .rule(FaultRule::new(Fault::StuckStatus).api("check_video").first_calls(10));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FaultInjection {
+    rules: Vec<FaultRule>,
+}
+
+impl FaultInjection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rule(mut self, rule: FaultRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// The former `rate_limit_emulation`: code 32 from `get_object`, `create_album`,
+    /// `paginate` and the items of `post_batch`.
+    pub fn rate_limit_emulation() -> Self {
+        Self::new().rule(
+            FaultRule::new(Fault::rate_limit())
+                .api("get_object")
+                .api("create_album")
+                .api("paginate")
+                .api("post_batch"),
+        )
+    }
+
+    fn fault_for(&self, api: &str) -> Option<&Fault> {
+        self.rules
+            .iter()
+            .find(|rule| rule.applies(api))
+            .map(|rule| &rule.fault)
+    }
+}
+
+impl Fbapi {
+    /// Response to use instead of sending `request`, if a fault applies to it.
+    pub(crate) async fn inject_fault(
+        &self,
+        request: &GraphRequest,
+    ) -> Result<Option<reqwest::Response>, FbapiError> {
+        let fault = match self
+            .fault_injection
+            .as_ref()
+            .and_then(|faults| faults.fault_for(request.api_name()))
+        {
+            Some(fault) => fault.clone(),
+            None => return Ok(None),
+        };
+        let (status, body) = match fault {
+            Fault::GraphError(error) if request.api_name() == "post_batch" => {
+                (200, batch_error_body(request, &error))
+            }
+            Fault::GraphError(error) => (400, error.to_string()),
+            Fault::Response { status, body } => (status, body),
+            Fault::Timeout(duration) => {
+                sleep(duration).await;
+                return Err(FbapiError::InjectedTimeout);
+            }
+            Fault::ResponseTimeout(duration) => {
+                sleep(duration).await;
+                return Err(FbapiError::InjectedResponseTimeout);
+            }
+            Fault::MalformedJson => (200, r#"{"id": "1"#.to_owned()),
+            Fault::Slow(duration) => {
+                sleep(duration).await;
+                return Ok(None);
+            }
+            Fault::StuckStatus => match status_body(request.api_name()) {
+                Some(body) => (200, body.to_string()),
+                None => return Ok(None),
+            },
+        };
+        let response = http::Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(body)
+            .map_err(|e| FbapiError::UnExpected(json!({ "fault_injection": e.to_string() })))?;
+        Ok(Some(response.into()))
+    }
+}
+
+/// "In progress" answer of the status checks.
+fn status_body(api: &str) -> Option<serde_json::Value> {
+    match api {
+        "check_video" => Some(json!({"status": {"video_status": "processing"}})),
+        "check_video_reel" => Some(json!({
+            "status": {
+                "uploading_phase": {"status": "in_progress"},
+                "copyright_check_status": {"status": "in_progress"},
+                "processing_phase": {"status": "in_progress"},
+                "publishing_phase": {"status": "not_started"},
+            }
+        })),
+        "check_ig_media" => Some(json!({"status": "IN_PROGRESS", "status_code": "IN_PROGRESS"})),
+        _ => None,
+    }
+}
+
+fn batch_error_body(request: &GraphRequest, error: &serde_json::Value) -> String {
+    let count = request
+        .form_value("batch")
+        .and_then(|batch| serde_json::from_str::<Vec<serde_json::Value>>(batch).ok())
+        .map(|items| items.len())
+        .unwrap_or(1);
+    let item = json!({
+        "code": 400,
+        "headers": [],
+        "body": error.to_string(),
+    });
+    serde_json::Value::Array(vec![item; count]).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_triggers() {
+        let rule = FaultRule::new(Fault::rate_limit())
+            .api("get_object")
+            .first_calls(2);
+        assert!(!rule.applies("post_object"));
+        assert!(rule.applies("get_object"));
+        assert!(rule.applies("get_object"));
+        assert!(!rule.applies("get_object"));
+
+        let rule = FaultRule::new(Fault::MalformedJson).nth_call(2);
+        assert!(!rule.applies("get_object"));
+        assert!(rule.applies("post_object"));
+        assert!(!rule.applies("get_object"));
+
+        let rule = FaultRule::new(Fault::StuckStatus);
+        assert!(!rule.applies("get_object"));
+        assert!(rule.applies("check_video"));
+
+        assert!(!FaultRule::new(Fault::MalformedJson)
+            .probability(0.0)
+            .applies("get_object"));
+        assert!(FaultRule::new(Fault::MalformedJson)
+            .probability(1.5)
+            .applies("get_object"));
+        assert!(!FaultRule::new(Fault::MalformedJson)
+            .probability(f64::NAN)
+            .applies("get_object"));
+        assert!(!FaultRule::new(Fault::MalformedJson)
+            .trigger(Trigger::Probability(-1.0))
+            .applies("get_object"));
+    }
+
+    #[tokio::test]
+    async fn test_inject_fault() {
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(
+                        FaultRule::new(Fault::graph_error(190, Some(460), "expired"))
+                            .api("get_object"),
+                    )
+                    .rule(FaultRule::new(Fault::StuckStatus).first_calls(1)),
+            )
+            .build()
+            .unwrap();
+        let credentials = Credentials::new("EAAtoken");
+        let err = api
+            .get_object(
+                &credentials,
+                "123",
+                "",
+                &[],
+                Some(&RetryPolicy::no_retry()),
+                |_| {},
+            )
+            .await
+            .unwrap_err();
+        assert!(err.is_invalid_token());
+        assert_eq!(err.error_subcode(), Some(460));
+
+        let batch = batch_request::Builder::new()
+            .get("me", &[])
+            .get("123", &[])
+            .build()
+            .unwrap();
+        let api = Fbapi::new("v19.0", 10, true).unwrap();
+        let items = api
+            .post_batch(&credentials, batch, Some(&RetryPolicy::no_retry()), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap_err().error_code(), Some(32));
+    }
+}
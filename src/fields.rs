@@ -0,0 +1,183 @@
+use std::fmt;
+
+/// `fields` parameter of a Graph API read, rendered in the field expansion syntax.
+///
+/// ```
+/// # use fbapi_reqwest::fields::{Field, Fields};
+/// let fields = Fields::new()
+///     .field("id")
+///     .field(
+///         Field::new("comments")
+///             .limit(10)
+///             .summary(true)
+///             .fields(Fields::new().field("from").field("message")),
+///     );
+/// assert_eq!(
+///     fields.to_string(),
+///     "id,comments.limit(10).summary(true){from,message}"
+/// );
+/// ```
+///
+/// A `&str` converts into `Fields` as is, so hand-written field strings keep working.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Fields {
+    items: Vec<Field>,
+}
+
+/// One field of [`Fields`], with optional modifiers and nested fields.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    name: String,
+    modifiers: Vec<(String, String)>,
+    subfields: Fields,
+}
+
+impl Fields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn field(mut self, field: impl Into<Field>) -> Self {
+        self.items.push(field.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl Field {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            modifiers: vec![],
+            subfields: Fields::default(),
+        }
+    }
+
+    pub fn limit(self, limit: usize) -> Self {
+        self.modifier("limit", &limit.to_string())
+    }
+
+    /// Unix timestamp or any date `strtotime` understands.
+    pub fn since(self, since: &str) -> Self {
+        self.modifier("since", since)
+    }
+
+    /// Unix timestamp or any date `strtotime` understands.
+    pub fn until(self, until: &str) -> Self {
+        self.modifier("until", until)
+    }
+
+    pub fn summary(self, summary: bool) -> Self {
+        self.modifier("summary", if summary { "true" } else { "false" })
+    }
+
+    /// Any other modifier, e.g. `.modifier("type", "large")`.
+    pub fn modifier(mut self, name: &str, value: &str) -> Self {
+        self.modifiers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn fields(mut self, fields: Fields) -> Self {
+        self.subfields = fields;
+        self
+    }
+
+    pub fn subfield(mut self, field: impl Into<Field>) -> Self {
+        self.subfields.items.push(field.into());
+        self
+    }
+}
+
+impl fmt::Display for Fields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, field) in self.items.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            field.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        for (name, value) in &self.modifiers {
+            write!(f, ".{}({})", name, value)?;
+        }
+        if !self.subfields.is_empty() {
+            write!(f, "{{{}}}", self.subfields)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for Field {
+    fn from(name: &str) -> Self {
+        Field::new(name)
+    }
+}
+
+impl From<&str> for Fields {
+    fn from(fields: &str) -> Self {
+        if fields.is_empty() {
+            Fields::new()
+        } else {
+            Fields::new().field(fields)
+        }
+    }
+}
+
+impl From<&String> for Fields {
+    fn from(fields: &String) -> Self {
+        fields.as_str().into()
+    }
+}
+
+impl From<&Fields> for Fields {
+    fn from(fields: &Fields) -> Self {
+        fields.clone()
+    }
+}
+
+impl From<Field> for Fields {
+    fn from(field: Field) -> Self {
+        Fields::new().field(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested() {
+        let fields = Fields::new().field("id").field(
+            Field::new("posts")
+                .limit(5)
+                .since("1600000000")
+                .until("1700000000")
+                .subfield("message")
+                .subfield(
+                    Field::new("comments")
+                        .summary(true)
+                        .subfield("from")
+                        .subfield(Field::new("attachment").modifier("type", "large")),
+                ),
+        );
+        assert_eq!(
+            fields.to_string(),
+            "id,posts.limit(5).since(1600000000).until(1700000000){message,comments.summary(true){from,attachment.type(large)}}"
+        );
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Fields::from("id,name").to_string(), "id,name");
+        assert!(Fields::from("").is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::*;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+/// Limits of a multi-step flow (video upload, reel, Instagram container, ...).
+///
+/// Once `deadline` has passed since the flow started, or `cancel` is cancelled, the flow
+/// stops at its current step with `FbapiError::Timeout`. Without a deadline the client
+/// default of [`FbapiBuilder::flow_deadline`] applies.
+///
+/// `progress` is called when a step starts and on every status check.
+#[derive(Clone, Default)]
+pub struct FlowOptions {
+    pub deadline: Option<Duration>,
+    pub cancel: Option<CancellationToken>,
+    pub progress: Option<Arc<dyn Fn(PublishProgress) + Send + Sync>>,
+    /// Start of the enclosing flow, so that nested flows report the overall elapsed time.
+    started: Option<Instant>,
+}
+
+impl std::fmt::Debug for FlowOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FlowOptions")
+            .field("deadline", &self.deadline)
+            .field("cancel", &self.cancel)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+/// Where a flow stands, reported to [`FlowOptions::progress`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublishProgress {
+    /// Step (`upload`, `thumbnail`, `publish`) or polled phase (`video_processing`,
+    /// `ig_container`, `reel_uploading`, `reel_copyright_check`, `reel_processing`,
+    /// `reel_publishing`).
+    pub phase: String,
+    /// `started` for a step, `transfer` after each chunk of a chunked upload and `finished`
+    /// once it is closed, the status reported by Facebook for a polled phase
+    /// (`matches_found` when the copyright check of a reel found a match).
+    pub status: String,
+    /// Completion in percent, when Facebook reports it (`processing_progress`) or of the
+    /// bytes sent by a chunked upload.
+    pub percent: Option<u8>,
+    /// Time since the flow started.
+    pub elapsed: Duration,
+    /// Session and next range of a chunked upload, with each `transfer` report.
+    pub upload: Option<UploadSession>,
+}
+
+impl FlowOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn progress(mut self, progress: impl Fn(PublishProgress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+}
+
+/// A running flow: its start and deadline as instants, its cancellation token and its
+/// progress callback.
+pub(crate) struct Flow {
+    started: Instant,
+    deadline_at: Instant,
+    cancel: Option<CancellationToken>,
+    progress: Option<Arc<dyn Fn(PublishProgress) + Send + Sync>>,
+}
+
+impl Flow {
+    /// Options for a nested flow that keep the time left, the token and the progress
+    /// callback of this one.
+    pub(crate) fn options(&self) -> FlowOptions {
+        FlowOptions {
+            deadline: Some(self.deadline_at.saturating_duration_since(Instant::now())),
+            cancel: self.cancel.clone(),
+            progress: self.progress.clone(),
+            started: Some(self.started),
+        }
+    }
+
+    pub(crate) fn report(&self, phase: &str, status: &str, percent: Option<u8>) {
+        self.report_upload(phase, status, percent, None);
+    }
+
+    /// Report along with the state of a chunked upload.
+    pub(crate) fn report_upload(
+        &self,
+        phase: &str,
+        status: &str,
+        percent: Option<u8>,
+        upload: Option<UploadSession>,
+    ) {
+        if let Some(progress) = &self.progress {
+            progress(PublishProgress {
+                phase: phase.to_owned(),
+                status: status.to_owned(),
+                percent,
+                elapsed: self.started.elapsed(),
+                upload,
+            });
+        }
+    }
+
+    /// Check the limits and report the start of the step `phase`.
+    pub(crate) fn start_step(&self, phase: &str) -> Result<(), FbapiError> {
+        self.check(phase, None)?;
+        self.report(phase, "started", None);
+        Ok(())
+    }
+
+    /// Run the step `phase` within the limits, reporting its start.
+    pub(crate) async fn step<T>(
+        &self,
+        phase: &str,
+        step: impl Future<Output = Result<T, FbapiError>>,
+    ) -> Result<T, FbapiError> {
+        self.start_step(phase)?;
+        self.run(phase, None, step).await
+    }
+
+    /// Fail when the deadline has passed or the flow was cancelled.
+    pub(crate) fn check(&self, phase: &str, last_status: Option<&str>) -> Result<(), FbapiError> {
+        let cancelled = matches!(&self.cancel, Some(cancel) if cancel.is_cancelled());
+        if cancelled || Instant::now() >= self.deadline_at {
+            return Err(timeout(phase, last_status));
+        }
+        Ok(())
+    }
+
+    /// Run one step of the flow, abandoning it when the deadline passes or the flow is
+    /// cancelled first.
+    pub(crate) async fn run<T>(
+        &self,
+        phase: &str,
+        last_status: Option<&str>,
+        step: impl Future<Output = Result<T, FbapiError>>,
+    ) -> Result<T, FbapiError> {
+        self.check(phase, last_status)?;
+        let cancelled = async {
+            match &self.cancel {
+                Some(cancel) => cancel.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            result = step => result,
+            _ = tokio::time::sleep_until(self.deadline_at.into()) => {
+                Err(timeout(phase, last_status))
+            }
+            _ = cancelled => Err(timeout(phase, last_status)),
+        }
+    }
+
+    /// Wait `duration` between two steps or status checks.
+    pub(crate) async fn sleep(
+        &self,
+        duration: Duration,
+        phase: &str,
+        last_status: Option<&str>,
+    ) -> Result<(), FbapiError> {
+        self.run(phase, last_status, async {
+            sleep(duration).await;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn timeout(phase: &str, last_status: Option<&str>) -> FbapiError {
+    FbapiError::Timeout {
+        phase: phase.to_owned(),
+        last_status: last_status.map(|s| s.to_owned()),
+    }
+}
+
+/// Deadline of a flow whose deadline is too long for an `Instant`, as tokio does for its
+/// own timers: about 30 years from `now`.
+fn far_future(now: Instant) -> Instant {
+    now + Duration::from_secs(86400 * 365 * 30)
+}
+
+impl Fbapi {
+    pub(crate) fn start_flow(&self, options: Option<&FlowOptions>) -> Flow {
+        let deadline = options
+            .and_then(|options| options.deadline)
+            .unwrap_or(self.flow_deadline);
+        let now = Instant::now();
+        Flow {
+            started: options.and_then(|options| options.started).unwrap_or(now),
+            deadline_at: now.checked_add(deadline).unwrap_or_else(|| far_future(now)),
+            cancel: options.and_then(|options| options.cancel.clone()),
+            progress: options.and_then(|options| options.progress.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_deadline_and_cancel() {
+        let api = Fbapi::new("v19.0", 10, false).unwrap();
+        let flow = api.start_flow(Some(
+            &FlowOptions::new().deadline(Duration::from_millis(10)),
+        ));
+        match flow
+            .sleep(
+                Duration::from_secs(5),
+                "reel_processing",
+                Some("in_progress"),
+            )
+            .await
+        {
+            Err(FbapiError::Timeout { phase, last_status }) => {
+                assert_eq!(phase, "reel_processing");
+                assert_eq!(last_status.as_deref(), Some("in_progress"));
+            }
+            other => panic!("Expected Timeout: {:?}", other),
+        }
+        assert!(flow.check("reel_publishing", None).is_err());
+
+        let cancel = CancellationToken::new();
+        let flow = api.start_flow(Some(&FlowOptions::new().cancel(cancel.clone())));
+        assert!(flow.check("upload", None).is_ok());
+        cancel.cancel();
+        assert!(matches!(
+            flow.sleep(Duration::from_secs(5), "video_processing", None)
+                .await,
+            Err(FbapiError::Timeout { .. })
+        ));
+
+        let flow = api.start_flow(Some(&FlowOptions::new().deadline(Duration::MAX)));
+        assert!(flow.check("upload", None).is_ok());
+    }
+}
@@ -0,0 +1,576 @@
+use crate::*;
+use reqwest::{multipart::Form, Method};
+use std::time::Instant;
+
+/// Internal description of one Graph API call.
+///
+/// Every `apis::*` module builds its requests with this type so that query strings,
+/// form bodies and multipart bodies are encoded by reqwest and the access token is
+/// attached in a single place.
+#[derive(Clone, Debug)]
+pub(crate) struct GraphRequest {
+    method: Method,
+    url: String,
+    query: Vec<(String, String)>,
+    form: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    auth: Option<Auth>,
+    /// API or flow step the request belongs to, used to scope fault injection.
+    api: &'static str,
+    /// Set on non-idempotent requests: looked up before a retry to avoid publishing twice.
+    published_check: Option<Box<PublishedCheck>>,
+}
+
+/// Request reading the state left by an earlier attempt, and whether its response shows
+/// that attempt took effect.
+#[derive(Clone)]
+pub(crate) struct PublishedCheck {
+    request: GraphRequest,
+    is_published: std::sync::Arc<dyn Fn(&serde_json::Value) -> bool + Send + Sync>,
+}
+
+impl std::fmt::Debug for PublishedCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PublishedCheck")
+            .field("request", &self.request)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Auth {
+    credentials: Credentials,
+    /// Resolved against the client default when the request is executed.
+    mode: Option<AuthMode>,
+}
+
+impl GraphRequest {
+    pub(crate) fn new(method: Method, url: String) -> Self {
+        Self {
+            method,
+            url,
+            query: vec![],
+            form: vec![],
+            headers: vec![],
+            auth: None,
+            api: "",
+            published_check: None,
+        }
+    }
+
+    pub(crate) fn get(url: String) -> Self {
+        Self::new(Method::GET, url)
+    }
+
+    pub(crate) fn post(url: String) -> Self {
+        Self::new(Method::POST, url)
+    }
+
+    pub(crate) fn delete(url: String) -> Self {
+        Self::new(Method::DELETE, url)
+    }
+
+    pub(crate) fn api(mut self, api: &'static str) -> Self {
+        self.api = api;
+        self
+    }
+
+    pub(crate) fn api_name(&self) -> &'static str {
+        self.api
+    }
+
+    /// Before retrying, send `request` and give up with `FbapiError::AlreadyPublished`
+    /// when `is_published` holds for its response.
+    pub(crate) fn published_check(
+        mut self,
+        request: GraphRequest,
+        is_published: impl Fn(&serde_json::Value) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.published_check = Some(Box::new(PublishedCheck {
+            request,
+            is_published: std::sync::Arc::new(is_published),
+        }));
+        self
+    }
+
+    pub(crate) fn query(mut self, key: &str, value: &str) -> Self {
+        self.query.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub(crate) fn queries(mut self, params: &[(&str, &str)]) -> Self {
+        for (key, value) in params {
+            self.query.push(((*key).to_owned(), (*value).to_owned()));
+        }
+        self
+    }
+
+    /// `fields` query parameter, omitted when empty.
+    pub(crate) fn fields(self, fields: impl Into<Fields>) -> Self {
+        let fields = fields.into();
+        if fields.is_empty() {
+            self
+        } else {
+            self.query("fields", &fields.to_string())
+        }
+    }
+
+    pub(crate) fn form(mut self, key: &str, value: &str) -> Self {
+        self.form.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub(crate) fn forms(mut self, params: &[(&str, &str)]) -> Self {
+        for (key, value) in params {
+            self.form.push(((*key).to_owned(), (*value).to_owned()));
+        }
+        self
+    }
+
+    pub(crate) fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub(crate) fn auth(mut self, credentials: &Credentials) -> Self {
+        self.auth = Some(Auth {
+            credentials: credentials.clone(),
+            mode: credentials.auth_mode(),
+        });
+        self
+    }
+
+    /// Always send the token as a header, for endpoints that only accept it there (rupload).
+    pub(crate) fn auth_header(mut self, credentials: &Credentials) -> Self {
+        self.auth = Some(Auth {
+            credentials: credentials.clone(),
+            mode: Some(AuthMode::Header),
+        });
+        self
+    }
+
+    /// Use `default` unless the credentials chose where to send the token.
+    pub(crate) fn default_auth_mode(mut self, default: AuthMode) -> Self {
+        if let Some(auth) = &mut self.auth {
+            auth.mode.get_or_insert(default);
+        }
+        self
+    }
+
+    pub(crate) fn form_value(&self, key: &str) -> Option<&str> {
+        self.form
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Url without the query string.
+    pub(crate) fn base_url(&self) -> &str {
+        &self.url
+    }
+
+    pub(crate) fn credentials(&self) -> Option<&Credentials> {
+        self.auth.as_ref().map(|auth| &auth.credentials)
+    }
+
+    fn auth_mode(&self) -> Option<AuthMode> {
+        self.auth
+            .as_ref()
+            .map(|auth| auth.mode.unwrap_or(AuthMode::Header))
+    }
+
+    /// `access_token` (unless sent as a header) and `appsecret_proof`.
+    fn auth_params(&self) -> Vec<(String, String)> {
+        let mut params = vec![];
+        let credentials = match self.credentials() {
+            Some(credentials) => credentials,
+            None => return params,
+        };
+        if self.auth_mode() == Some(AuthMode::Parameter) {
+            params.push(("access_token".to_owned(), credentials.access_token()));
+        }
+        if let Some(appsecret_proof) = credentials.appsecret_proof() {
+            params.push(("appsecret_proof".to_owned(), appsecret_proof));
+        }
+        params
+    }
+
+    /// Whether sending the request again cannot repeat a side effect.
+    pub(crate) fn is_idempotent(&self) -> bool {
+        self.method == Method::GET
+    }
+
+    fn has_body(&self) -> bool {
+        self.method != Method::GET && self.method != Method::DELETE
+    }
+
+    /// Whether the auth parameters go in the body. A request that sends the token as a
+    /// header and has no form parameters (a rupload, whose body is the upload itself) keeps
+    /// them in the query string.
+    fn auth_in_body(&self) -> bool {
+        self.has_body() && !(self.auth_mode() == Some(AuthMode::Header) && self.form.is_empty())
+    }
+
+    /// Query parameters as sent, including the access token for bodiless requests.
+    fn query_params(&self) -> Vec<(String, String)> {
+        let mut params = self.query.clone();
+        if !self.auth_in_body() {
+            params.extend(self.auth_params());
+        }
+        params
+    }
+
+    /// Body parameters as sent, including the access token for requests with a body.
+    fn form_params(&self) -> Vec<(String, String)> {
+        if self.auth_in_body() {
+            self.auth_params()
+                .into_iter()
+                .chain(self.form.clone())
+                .collect()
+        } else {
+            self.form.clone()
+        }
+    }
+
+    /// Full URL with the percent-encoded query string.
+    pub(crate) fn url(&self) -> String {
+        let query = self.query_params();
+        if query.is_empty() {
+            return self.url.clone();
+        }
+        let encoded = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(query)
+            .finish();
+        format!("{}?{}", self.url, encoded)
+    }
+
+    pub(crate) fn log_params(&self) -> LogParams {
+        let params = self.form_params();
+        LogParams::new(
+            &self.url(),
+            &params
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn request_builder(&self, client: &reqwest::Client) -> reqwest::RequestBuilder {
+        let mut builder = client.request(self.method.clone(), &self.url);
+        let query = self.query_params();
+        if !query.is_empty() {
+            builder = builder.query(&query);
+        }
+        for (key, value) in &self.headers {
+            builder = builder.header(key.as_str(), value.as_str());
+        }
+        if let (Some(AuthMode::Header), Some(credentials)) = (self.auth_mode(), self.credentials())
+        {
+            builder = builder.header(
+                "Authorization",
+                format!("OAuth {}", credentials.access_token()),
+            );
+        }
+        builder
+    }
+
+    pub(crate) async fn send(
+        &self,
+        client: &reqwest::Client,
+    ) -> Result<reqwest::Response, FbapiError> {
+        let mut builder = self.request_builder(client);
+        let form = self.form_params();
+        if !form.is_empty() {
+            builder = builder.form(&form);
+        }
+        builder.send().await.map_err(|e| e.into())
+    }
+
+    /// Send the request with a multipart body; the form parameters and the access token
+    /// are added to `form` as text fields.
+    pub(crate) async fn send_multipart(
+        &self,
+        client: &reqwest::Client,
+        mut form: Form,
+    ) -> Result<reqwest::Response, FbapiError> {
+        for (key, value) in self.form_params() {
+            form = form.text(key, value);
+        }
+        self.request_builder(client)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| e.into())
+    }
+}
+
+impl Fbapi {
+    pub(crate) async fn execute(
+        &self,
+        request: &GraphRequest,
+        retry: Option<&RetryPolicy>,
+        log: &impl Fn(LogEvent),
+    ) -> Result<serde_json::Value, FbapiError> {
+        self.execute_with(&self.client, request, retry, log).await
+    }
+
+    /// Same as `execute`, also returning the usage headers of the response.
+    pub(crate) async fn execute_usage(
+        &self,
+        request: &GraphRequest,
+        retry: Option<&RetryPolicy>,
+        log: &impl Fn(LogEvent),
+    ) -> Result<(serde_json::Value, Usage), FbapiError> {
+        self.execute_with_usage(&self.client, request, retry, log)
+            .await
+    }
+
+    pub(crate) async fn execute_with(
+        &self,
+        client: &reqwest::Client,
+        request: &GraphRequest,
+        retry: Option<&RetryPolicy>,
+        log: &impl Fn(LogEvent),
+    ) -> Result<serde_json::Value, FbapiError> {
+        self.execute_with_usage(client, request, retry, log)
+            .await
+            .map(|(json, _)| json)
+    }
+
+    /// Execute with retries; after an invalid token error the token is refreshed through
+    /// the [`TokenProvider`] and the request is sent once more.
+    pub(crate) async fn execute_with_usage(
+        &self,
+        client: &reqwest::Client,
+        request: &GraphRequest,
+        retry: Option<&RetryPolicy>,
+        log: &impl Fn(LogEvent),
+    ) -> Result<(serde_json::Value, Usage), FbapiError> {
+        let request = &request.clone().default_auth_mode(self.auth_mode);
+        self.execute_refreshing(client, request, retry, log, || {
+            self.send_or_inject(client, request)
+        })
+        .await
+    }
+
+    /// Send a multipart request once, with the same token refresh as `execute_with_usage`.
+    /// A multipart body can be sent only once, so `form` builds a new one for every attempt.
+    pub(crate) async fn execute_form(
+        &self,
+        request: &GraphRequest,
+        form: impl Fn() -> Result<Form, FbapiError>,
+        log: &impl Fn(LogEvent),
+    ) -> Result<(serde_json::Value, Usage), FbapiError> {
+        self.execute_form_with(
+            &self.client,
+            request,
+            form,
+            Some(&RetryPolicy::no_retry()),
+            log,
+        )
+        .await
+    }
+
+    /// Same as `execute_form` with retries, sent through `client`.
+    pub(crate) async fn execute_form_with(
+        &self,
+        client: &reqwest::Client,
+        request: &GraphRequest,
+        form: impl Fn() -> Result<Form, FbapiError>,
+        retry: Option<&RetryPolicy>,
+        log: &impl Fn(LogEvent),
+    ) -> Result<(serde_json::Value, Usage), FbapiError> {
+        let request = &request.clone().default_auth_mode(self.auth_mode);
+        self.execute_refreshing(client, request, retry, log, || async {
+            match self.inject_fault(request).await? {
+                Some(response) => Ok(response),
+                None => request.send_multipart(client, form()?).await,
+            }
+        })
+        .await
+    }
+
+    /// Retry loop of `send`, then a token refresh and a second loop after an invalid token
+    /// error. `request` is only used for logging, throttling and its credentials.
+    async fn execute_refreshing<Send, SendFuture>(
+        &self,
+        client: &reqwest::Client,
+        request: &GraphRequest,
+        retry: Option<&RetryPolicy>,
+        log: &impl Fn(LogEvent),
+        send: Send,
+    ) -> Result<(serde_json::Value, Usage), FbapiError>
+    where
+        Send: Fn() -> SendFuture,
+        SendFuture: Future<Output = Result<reqwest::Response, FbapiError>>,
+    {
+        let log = &|event| self.emit(log, event);
+        let retry = retry.unwrap_or(&self.retry_policy);
+        self.throttle(request).await?;
+        let used_token = request.credentials().map(|c| c.access_token());
+        let published_check = request
+            .published_check
+            .as_deref()
+            .map(|check| move || self.check_published(client, check, log));
+        let error = match execute_retry(
+            retry,
+            self.retry_classifier.as_ref(),
+            &send,
+            published_check.as_ref(),
+            request.is_idempotent(),
+            log,
+            request.log_params(),
+        )
+        .await
+        {
+            Ok(received) => return Ok(received),
+            Err(error) => error,
+        };
+        if let (Some(credentials), Some(used_token)) = (request.credentials(), used_token) {
+            if self.refresh_token(credentials, &used_token, &error).await? {
+                self.throttle(request).await?;
+                return execute_retry(
+                    retry,
+                    self.retry_classifier.as_ref(),
+                    &send,
+                    published_check.as_ref(),
+                    request.is_idempotent(),
+                    log,
+                    request.log_params(),
+                )
+                .await;
+            }
+        }
+        Err(error)
+    }
+
+    /// Send the request of `check`; the response when it shows an earlier attempt
+    /// took effect.
+    async fn check_published(
+        &self,
+        client: &reqwest::Client,
+        check: &PublishedCheck,
+        log: &impl Fn(LogEvent),
+    ) -> Result<Option<serde_json::Value>, FbapiError> {
+        let request = check.request.clone().default_auth_mode(self.auth_mode);
+        let log_params = request.log_params();
+        log(LogEvent::RequestStarted {
+            request: log_params.clone(),
+            attempt: 0,
+        });
+        let started = Instant::now();
+        let response = self.send_or_inject(client, &request).await?;
+        let (json, _) = receive(response, &log_params, started, log).await?;
+        Ok((check.is_published)(&json).then_some(json))
+    }
+
+    async fn send_or_inject(
+        &self,
+        client: &reqwest::Client,
+        request: &GraphRequest,
+    ) -> Result<reqwest::Response, FbapiError> {
+        match self.inject_fault(request).await? {
+            Some(response) => Ok(response),
+            None => request.send(client).await,
+        }
+    }
+
+    /// Redact `event`, emit it through `tracing` when the feature is on and pass it to `log`.
+    /// The usage of a received response is recorded in the usage registry first.
+    pub(crate) fn emit(&self, log: &impl Fn(LogEvent), event: LogEvent) {
+        if let LogEvent::ResponseReceived { usage, .. } = &event {
+            self.usage_registry.update(&self.app_id, usage);
+        }
+        let event = self.redaction.apply(event);
+        #[cfg(feature = "tracing")]
+        event.trace();
+        log(event);
+    }
+
+    /// Log a status read while waiting on Facebook in an upload flow.
+    pub(crate) fn log_poll_status(
+        &self,
+        request: &GraphRequest,
+        phase: &str,
+        status: &str,
+        log: &impl Fn(LogEvent),
+    ) {
+        self.emit(
+            log,
+            LogEvent::PollStatus {
+                request: request.log_params(),
+                phase: phase.to_owned(),
+                status: status.to_owned(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_is_encoded() {
+        let request = GraphRequest::get("https://graph.facebook.com/v19.0/123".to_owned())
+            .query("fields", "comments.limit(10){from,message}")
+            .auth(&Credentials::new("to+k&en").with_auth_mode(AuthMode::Parameter));
+        assert_eq!(
+            request.url(),
+            "https://graph.facebook.com/v19.0/123?fields=comments.limit%2810%29%7Bfrom%2Cmessage%7D&access_token=to%2Bk%26en"
+        );
+    }
+
+    #[test]
+    fn test_token_is_not_in_url_by_default() {
+        let request = GraphRequest::get("https://graph.facebook.com/v19.0/123".to_owned())
+            .query("fields", "status")
+            .auth(&Credentials::new("token"))
+            .default_auth_mode(AuthMode::Header);
+        assert_eq!(
+            request.url(),
+            "https://graph.facebook.com/v19.0/123?fields=status"
+        );
+        let built = request
+            .request_builder(&reqwest::Client::new())
+            .build()
+            .unwrap();
+        assert_eq!(built.headers()["Authorization"], "OAuth token");
+    }
+
+    #[test]
+    fn test_auth_goes_to_form_for_post() {
+        let request = GraphRequest::post("https://graph.facebook.com/v19.0/123/feed".to_owned())
+            .form("message", "hello world")
+            .auth(&Credentials::new("token").with_app_secret("secret"))
+            .default_auth_mode(AuthMode::Parameter);
+        assert_eq!(request.url(), "https://graph.facebook.com/v19.0/123/feed");
+        let params = request.form_params();
+        assert_eq!(params[0], ("access_token".to_owned(), "token".to_owned()));
+        assert_eq!(params[1].0, "appsecret_proof");
+        assert_eq!(params[1].1, sign("token", "secret"));
+        assert_eq!(params[2], ("message".to_owned(), "hello world".to_owned()));
+    }
+
+    #[test]
+    fn test_auth_header_is_not_in_params() {
+        let request =
+            GraphRequest::post("https://rupload.facebook.com/video-upload/v19.0/1".to_owned())
+                .auth_header(&Credentials::new("token").with_app_secret("secret"))
+                .default_auth_mode(AuthMode::Parameter);
+        assert!(request.form_params().is_empty());
+        assert_eq!(
+            request.url(),
+            format!(
+                "https://rupload.facebook.com/video-upload/v19.0/1?appsecret_proof={}",
+                sign("token", "secret")
+            )
+        );
+        let built = request
+            .request_builder(&reqwest::Client::new())
+            .build()
+            .unwrap();
+        assert_eq!(built.headers()["Authorization"], "OAuth token");
+        assert!(built.body().is_none());
+    }
+}
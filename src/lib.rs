@@ -1,22 +1,98 @@
+#![allow(clippy::too_many_arguments)]
+
+/// Wrap an async method in a `tracing` span when the `tracing` feature is enabled.
+/// The span has the `api` and `node` given here, the API `version` and an empty
+/// `fbtrace_id` filled in from the response. Arguments are not recorded.
+// The receiver, `&` included, is passed through from the method so that `self` resolves in
+// the code `tracing::instrument` generates for it.
+macro_rules! instrument {
+    (
+        api = $api:literal $(, node = $node:expr)?, version = $version:expr;
+        $(#[$attr:meta])*
+        $vis:vis async fn $name:ident $(<$gen:ident: $bound:path>)? ($amp:tt $self:ident $($args:tt)*)
+            -> $ret:ty $body:block
+    ) => {
+        $(#[$attr])*
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(
+                skip_all,
+                fields(
+                    api = $api,
+                    $(node = %$node,)?
+                    version = %$version,
+                    fbtrace_id = tracing::field::Empty,
+                )
+            )
+        )]
+        $vis async fn $name $(<$gen: $bound>)? ($amp $self $($args)*) -> $ret $body
+    };
+    (
+        api = $api:literal $(, node = $node:expr)?;
+        $(#[$attr:meta])*
+        $vis:vis async fn $name:ident $(<$gen:ident: $bound:path>)? ($amp:tt $self:ident $($args:tt)*)
+            -> $ret:ty $body:block
+    ) => {
+        instrument! {
+            api = $api $(, node = $node)?, version = $self.version;
+            $(#[$attr])*
+            $vis async fn $name $(<$gen: $bound>)? ($amp $self $($args)*) -> $ret $body
+        }
+    };
+}
+
 pub mod apis;
 pub mod batch_request;
+pub mod builder;
+pub mod credentials;
 pub mod error;
+pub mod fault_injection;
+pub mod fields;
+pub mod flow;
+mod graph_request;
+pub mod log_event;
+pub mod poller;
+pub mod publish_handle;
+pub mod redaction;
+pub mod responses;
+pub mod retry;
+pub mod throttle;
+pub mod token_provider;
+pub mod usage;
 
 #[macro_use]
 extern crate serde_json;
 
 use crate::error::FbapiError;
+use crate::graph_request::GraphRequest;
 use crypto::mac::Mac;
 use once_cell::sync::Lazy;
-use reqwest::{multipart::Part, Body};
-use std::{future::Future, time::Duration};
+use reqwest::multipart::Part;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
 use tokio::time::sleep;
 
+pub use builder::FbapiBuilder;
+pub use credentials::{AuthMode, Credentials};
+pub use fault_injection::{Fault, FaultInjection, FaultRule};
+pub use fields::{Field, Fields};
+pub use flow::{FlowOptions, PublishProgress};
+pub use log_event::{LogEvent, LogParams};
+pub use poller::PollPolicy;
+pub use publish_handle::{PublishHandle, PublishPhase, PublishTarget, UploadSession};
+pub use redaction::Redaction;
 pub use reqwest;
+pub use retry::{DefaultRetryClassifier, RetryClassifier, RetryPolicy};
+pub use throttle::ThrottlePolicy;
+pub use token_provider::TokenProvider;
+pub use tokio_util::sync::CancellationToken;
+pub use usage::{AdAccountUsage, AppUsage, BucUsage, Usage, UsageRegistry};
 
-const GRAPH_PREFIX: &'static str = "https://graph.facebook.com/";
-const VIDEO_PREFIX: &'static str = "https://graph-video.facebook.com/";
-const VIDEO_REEL_URL_PREFIX: &'static str = "https://rupload.facebook.com/video-upload/";
+const GRAPH_PREFIX: &str = "https://graph.facebook.com/";
+const VIDEO_PREFIX: &str = "https://graph-video.facebook.com/";
+const VIDEO_REEL_URL_PREFIX: &str = "https://rupload.facebook.com/video-upload/";
 
 static ERROR_VALUE: Lazy<serde_json::Value> = Lazy::new(|| {
     json!({
@@ -32,7 +108,20 @@ static ERROR_VALUE: Lazy<serde_json::Value> = Lazy::new(|| {
 pub struct Fbapi {
     client: reqwest::Client,
     version: String,
-    rate_limit_emulation: bool,
+    graph_url: String,
+    graph_video_url: String,
+    rupload_url: String,
+    fault_injection: Option<FaultInjection>,
+    retry_policy: RetryPolicy,
+    retry_classifier: std::sync::Arc<dyn RetryClassifier>,
+    poll_policy: PollPolicy,
+    flow_deadline: Duration,
+    token_provider: Option<std::sync::Arc<dyn TokenProvider>>,
+    auth_mode: AuthMode,
+    redaction: Redaction,
+    usage_registry: UsageRegistry,
+    app_id: String,
+    throttle_policy: Option<ThrottlePolicy>,
 }
 
 impl Fbapi {
@@ -41,23 +130,52 @@ impl Fbapi {
         timeout_seconds: u64,
         rate_limit_emulation: bool,
     ) -> Result<Self, FbapiError> {
-        Ok(Self {
-            client: Self::make_client(timeout_seconds)?,
-            version: version.to_owned(),
-            rate_limit_emulation: rate_limit_emulation,
-        })
+        Self::builder(version)
+            .timeout(Duration::from_secs(timeout_seconds))
+            .rate_limit_emulation(rate_limit_emulation)
+            .build()
+    }
+
+    pub fn builder(version: &str) -> FbapiBuilder {
+        FbapiBuilder::new(version)
+    }
+
+    /// Default retry policy configured with [`FbapiBuilder::retry_policy`].
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Polling of the flows that take no `poll` of their own, configured with
+    /// [`FbapiBuilder::poll_policy`].
+    pub fn poll_policy(&self) -> &PollPolicy {
+        &self.poll_policy
+    }
+
+    /// `poll` when given, the client [`PollPolicy`] otherwise.
+    pub(crate) fn poll_policy_or(&self, poll: Option<&PollPolicy>) -> PollPolicy {
+        poll.unwrap_or(&self.poll_policy).clone()
+    }
+
+    /// Deadline of flows not given their own, configured with [`FbapiBuilder::flow_deadline`].
+    pub fn flow_deadline(&self) -> Duration {
+        self.flow_deadline
+    }
+
+    /// Latest rate limit usage reported by Facebook to this client.
+    pub fn usage_registry(&self) -> &UsageRegistry {
+        &self.usage_registry
     }
 
     fn make_path(&self, postfix: &str) -> String {
-        format!("{}{}/{}", GRAPH_PREFIX, self.version, postfix)
+        format!("{}{}/{}", self.graph_url, self.version, postfix)
     }
 
     fn make_video_path(&self, postfix: &str) -> String {
-        format!("{}{}/{}", VIDEO_PREFIX, self.version, postfix)
+        format!("{}{}/{}", self.graph_video_url, self.version, postfix)
     }
 
     fn make_video_reel_path(&self, video_id: &str) -> String {
-        format!("{}{}/{}", VIDEO_REEL_URL_PREFIX, self.version, video_id)
+        format!("{}{}/{}", self.rupload_url, self.version, video_id)
     }
 
     pub fn make_client(timeout_seconds: u64) -> Result<reqwest::Client, FbapiError> {
@@ -68,85 +186,126 @@ impl Fbapi {
     }
 }
 
-pub(crate) async fn execute_retry<Executor, ResponseFutuer>(
-    retry_count: usize,
+/// Run `executor` until it succeeds or the failure is not worth retrying.
+///
+/// When `published_check` is given the request is not idempotent: it is run after the delay
+/// before each retry, and a response showing the failed attempt took effect ends the loop
+/// with `FbapiError::AlreadyPublished`, logged as `LogEvent::AlreadyPublished`. The check is
+/// skipped when the failed attempt never reached Facebook. If the check itself fails, the
+/// original error is returned rather than risking a duplicate. An `idempotent` request, or
+/// one with a check, is classified with [`RetryClassifier::is_retryable_idempotent`].
+pub(crate) async fn execute_retry<Executor, ResponseFutuer, Check, CheckFuture>(
+    retry: &RetryPolicy,
+    classifier: &dyn RetryClassifier,
     executor: Executor,
-    log: &impl Fn(LogParams),
-    src_params: LogParams,
-) -> Result<serde_json::Value, FbapiError>
+    published_check: Option<&Check>,
+    idempotent: bool,
+    log: &impl Fn(LogEvent),
+    request: LogParams,
+) -> Result<(serde_json::Value, Usage), FbapiError>
 where
     ResponseFutuer: Future<Output = Result<reqwest::Response, FbapiError>>,
     Executor: Fn() -> ResponseFutuer,
+    CheckFuture: Future<Output = Result<Option<serde_json::Value>, FbapiError>>,
+    Check: Fn() -> CheckFuture,
 {
-    let mut count: usize = 0;
-    let mut last_error: FbapiError;
+    let first_started = Instant::now();
+    let mut attempt: usize = 0;
     loop {
-        let mut params = src_params.clone();
-        params.count = count;
-        log(params);
-        match executor().await {
+        log(LogEvent::RequestStarted {
+            request: request.clone(),
+            attempt,
+        });
+        let started = Instant::now();
+        let (status, error) = match executor().await {
             Ok(response) => {
-                // Extract rate limit headers before consuming response with json()
-                let app_usage = response
-                    .headers()
-                    .get("x-app-usage")
-                    .and_then(|v| v.to_str().ok())
-                    .map(|s| s.to_owned());
-                let business_use_case_usage = response
-                    .headers()
-                    .get("x-business-use-case-usage")
-                    .and_then(|v| v.to_str().ok())
-                    .map(|s| s.to_owned());
-
-                match response.json::<serde_json::Value>().await {
-                    Ok(json) => {
-                        if json["error"].is_object() {
-                            return Err(FbapiError::Facebook(json));
-                        } else {
-                            let mut params = src_params.clone();
-                            params.count = count;
-                            params.result = Some(json.clone());
-                            params.app_usage = app_usage;
-                            params.business_use_case_usage = business_use_case_usage;
-                            log(params);
-                            return Ok(json);
-                        }
-                    }
-                    Err(err) => last_error = err.into(),
+                let status = response.status().as_u16();
+                match receive(response, &request, started, log).await {
+                    Ok(received) => return Ok(received),
+                    Err(error) => (Some(status), error),
                 }
             }
-            Err(err) => last_error = err.into(),
+            Err(error) => (None, error),
+        };
+        record_error(&error);
+        let delay = retry.delay(attempt);
+        let retryable = if idempotent || published_check.is_some() {
+            classifier.is_retryable_idempotent(status, &error)
+        } else {
+            classifier.is_retryable(status, &error)
         };
-        count = count + 1;
-        if count >= retry_count {
-            break;
+        if !retryable || !retry.should_retry(attempt, first_started.elapsed(), delay) {
+            log(LogEvent::Failed {
+                request,
+                error: error.to_string(),
+            });
+            return Err(error);
         }
+        log(LogEvent::RetryScheduled {
+            request: request.clone(),
+            attempt,
+            delay,
+            error: error.to_string(),
+        });
+        sleep(delay).await;
+        // 待機中に反映されることもあるため、待機後に公開済みかを確認する。
+        if let Some(check) = published_check.filter(|_| !error.is_not_sent()) {
+            match check().await {
+                Ok(None) => {}
+                Ok(Some(response)) => {
+                    log(LogEvent::AlreadyPublished {
+                        request,
+                        attempt,
+                        result: response.clone(),
+                    });
+                    return Err(FbapiError::AlreadyPublished(response));
+                }
+                Err(_) => {
+                    log(LogEvent::Failed {
+                        request,
+                        error: error.to_string(),
+                    });
+                    return Err(error);
+                }
+            }
+        }
+        attempt += 1;
     }
-    Err(last_error)
 }
 
-pub(crate) async fn execute_form(
-    client: &reqwest::Client,
-    path: &str,
-    form: reqwest::multipart::Form,
-    log: &impl Fn(LogParams),
-    log_params: LogParams,
-) -> Result<serde_json::Value, FbapiError> {
-    log(log_params.clone());
-    let json: serde_json::Value = client
-        .post(path)
-        .multipart(form)
-        .send()
-        .await?
-        .json()
-        .await?;
-    let mut log_params = log_params.clone();
-    log_params.result = Some(json.clone());
-    log(log_params);
+/// Record the `fbtrace_id` of `error` on the span of the API call.
+#[cfg(feature = "tracing")]
+pub(crate) fn record_error(error: &FbapiError) {
+    if let Some(fbtrace_id) = error.fbtrace_id() {
+        tracing::Span::current().record("fbtrace_id", fbtrace_id);
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn record_error(_error: &FbapiError) {}
+
+/// Read the body of `response`, log it and turn a Graph API error into `FbapiError::Facebook`.
+pub(crate) async fn receive(
+    response: reqwest::Response,
+    request: &LogParams,
+    started: Instant,
+    log: &impl Fn(LogEvent),
+) -> Result<(serde_json::Value, Usage), FbapiError> {
+    let status = response.status().as_u16();
+    // Extract rate limit headers before consuming response with json()
+    let usage = Usage::from_headers(response.headers());
+    let json = response.json::<serde_json::Value>().await?;
+    log(LogEvent::ResponseReceived {
+        request: request.clone(),
+        status,
+        elapsed: started.elapsed(),
+        usage: usage.clone(),
+        result: json.clone(),
+    });
     if json["error"].is_object() {
         Err(FbapiError::Facebook(json))
     } else {
-        Ok(json)
+        Ok((json, usage))
     }
 }
 
@@ -160,33 +319,6 @@ fn sign(base: &str, key: &str) -> String {
         .collect()
 }
 
-#[derive(Clone)]
-pub struct LogParams {
-    pub path: String,
-    pub params: Vec<(String, String)>,
-    pub count: usize,
-    pub result: Option<serde_json::Value>,
-    pub app_usage: Option<String>,
-    pub business_use_case_usage: Option<String>,
-}
-
-impl LogParams {
-    fn new(path: &str, params: &Vec<(&str, &str)>) -> Self {
-        let mut dst = vec![];
-        for param in params {
-            dst.push((param.0.to_owned(), param.1.to_owned()));
-        }
-        Self {
-            path: path.to_owned(),
-            params: dst,
-            count: 0,
-            result: None,
-            app_usage: None,
-            business_use_case_usage: None,
-        }
-    }
-}
-
 /// Validates that a media ID is a valid non-zero numeric string.
 ///
 /// Returns an error if the ID is empty, "0", or contains non-digit characters.
@@ -203,35 +335,130 @@ pub(crate) fn validate_media_id(
     Ok(id.to_owned())
 }
 
-pub(crate) fn make_part(path: &str, bytes: rusoto_core::ByteStream) -> Result<Part, FbapiError> {
-    Part::stream(Body::wrap_stream(bytes))
+/// Read a whole upload into memory, so that its multipart body can be built again when the
+/// request is resent with a refreshed token.
+pub(crate) async fn read_bytes(mut bytes: rusoto_core::ByteStream) -> Result<Vec<u8>, FbapiError> {
+    use futures::StreamExt;
+    let mut buffer = vec![];
+    while let Some(chunk) = bytes.next().await {
+        buffer.extend_from_slice(&chunk?);
+    }
+    Ok(buffer)
+}
+
+pub(crate) fn make_part(path: &str, bytes: &[u8]) -> Result<Part, FbapiError> {
+    Part::bytes(bytes.to_vec())
         .file_name(path.to_owned())
         .mime_str("application/octet-stream")
         .map_err(|e| e.into())
 }
 
-pub(crate) async fn sleep_sec(seconds: usize) {
-    sleep(Duration::from_secs(seconds as u64)).await
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    type NoCheck = fn() -> std::future::Ready<Result<Option<serde_json::Value>, FbapiError>>;
+    const NO_CHECK: Option<&NoCheck> = None;
+
     #[tokio::test]
     async fn it_works() {
         let api = Fbapi::new("v8.0", 10, true).unwrap();
         let res = api
-            .get_object("xxxx", None, "aaa", "", &vec![], 2, |params| {
-                println!(
-                    "params {},{:?},{},{:?}",
-                    params.path, params.params, params.count, params.result
-                )
+            .get_object(&Credentials::new("xxxx"), "aaa", "", &[], None, |event| {
+                println!("event {:?}", event)
             })
             .await;
         println!("{:?}", res);
     }
 
+    #[tokio::test]
+    async fn test_execute_retry_events() {
+        let events = std::sync::Mutex::new(vec![]);
+        let res = execute_retry(
+            &RetryPolicy::default()
+                .max_attempts(2)
+                .initial_delay(Duration::from_millis(1)),
+            &DefaultRetryClassifier,
+            || async { Err(FbapiError::InjectedTimeout) },
+            NO_CHECK,
+            false,
+            &|event| events.lock().unwrap().push(event),
+            LogParams::new("https://graph.facebook.com/v19.0/me", &[]),
+        )
+        .await;
+        assert!(matches!(res, Err(FbapiError::InjectedTimeout)));
+        let events = events.into_inner().unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                LogEvent::RequestStarted { attempt: 0, .. },
+                LogEvent::RetryScheduled { attempt: 0, .. },
+                LogEvent::RequestStarted { attempt: 1, .. },
+                LogEvent::Failed { .. },
+            ]
+        ));
+    }
+
+    fn unavailable() -> FbapiError {
+        FbapiError::Facebook(json!({"error": {"code": 2, "message": "unavailable"}}))
+    }
+
+    #[tokio::test]
+    async fn test_execute_retry_published_check() {
+        let events = std::sync::Mutex::new(vec![]);
+        let res = execute_retry(
+            &RetryPolicy::default().initial_delay(Duration::from_millis(1)),
+            &DefaultRetryClassifier,
+            || async { Err(unavailable()) },
+            Some(&|| async { Ok(Some(json!({"status_code": "PUBLISHED"}))) }),
+            false,
+            &|event| events.lock().unwrap().push(event),
+            LogParams::new("https://graph.facebook.com/v19.0/1/media_publish", &[]),
+        )
+        .await;
+        match res {
+            Err(FbapiError::AlreadyPublished(response)) => {
+                assert_eq!(response["status_code"], "PUBLISHED")
+            }
+            other => panic!("Expected AlreadyPublished: {:?}", other),
+        }
+        assert!(matches!(
+            events.into_inner().unwrap().as_slice(),
+            [
+                LogEvent::RequestStarted { attempt: 0, .. },
+                LogEvent::RetryScheduled { attempt: 0, .. },
+                LogEvent::AlreadyPublished { attempt: 0, .. },
+            ]
+        ));
+
+        let res = execute_retry(
+            &RetryPolicy::default().initial_delay(Duration::from_millis(1)),
+            &DefaultRetryClassifier,
+            || async { Err(unavailable()) },
+            Some(&|| async { Err(FbapiError::InjectedTimeout) }),
+            false,
+            &|_| {},
+            LogParams::new("https://graph.facebook.com/v19.0/1/media_publish", &[]),
+        )
+        .await;
+        assert_eq!(res.unwrap_err().error_code(), Some(2));
+
+        // 送信されていない失敗は確認せずに再試行する。
+        let res = execute_retry(
+            &RetryPolicy::default()
+                .max_attempts(2)
+                .initial_delay(Duration::from_millis(1)),
+            &DefaultRetryClassifier,
+            || async { Err(FbapiError::InjectedTimeout) },
+            Some(&|| async { panic!("checked a request that was not sent") }),
+            false,
+            &|_| {},
+            LogParams::new("https://graph.facebook.com/v19.0/1/media_publish", &[]),
+        )
+        .await;
+        assert!(matches!(res, Err(FbapiError::InjectedTimeout)));
+    }
+
     #[test]
     fn test_validate_media_id_valid() {
         let response = json!({"id": "17841400123456789"});
@@ -0,0 +1,147 @@
+use crate::usage::Usage;
+use std::time::Duration;
+
+/// Request a [`LogEvent`] is about: the url (with its query) and the form parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogParams {
+    pub path: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl LogParams {
+    pub(crate) fn new(path: &str, params: &[(&str, &str)]) -> Self {
+        Self {
+            path: path.to_owned(),
+            params: params
+                .iter()
+                .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+                .collect(),
+        }
+    }
+}
+
+/// What the `log` callback of every API receives.
+///
+/// A successful call logs `RequestStarted` then `ResponseReceived`; a failed attempt logs
+/// `RetryScheduled` when another attempt follows and `Failed` otherwise, or
+/// `AlreadyPublished` when a publish is found to have gone through before its retry. Upload
+/// flows that wait on Facebook also log `PollStatus` for every status they read.
+#[derive(Clone, Debug)]
+pub enum LogEvent {
+    RequestStarted {
+        request: LogParams,
+        /// 0 for the first attempt.
+        attempt: usize,
+    },
+    RetryScheduled {
+        request: LogParams,
+        /// Attempt that failed.
+        attempt: usize,
+        /// Wait before the next attempt.
+        delay: Duration,
+        error: String,
+    },
+    ResponseReceived {
+        request: LogParams,
+        /// HTTP status code.
+        status: u16,
+        /// Time from sending the request to reading the whole body.
+        elapsed: Duration,
+        usage: Usage,
+        result: serde_json::Value,
+    },
+    PollStatus {
+        request: LogParams,
+        /// Step of the flow being waited on, e.g. `"ig_container"` or `"video_processing"`.
+        phase: String,
+        status: String,
+    },
+    Failed {
+        request: LogParams,
+        error: String,
+    },
+    /// The read done before retrying a publish showed the failed attempt took effect, so
+    /// no retry is sent.
+    AlreadyPublished {
+        request: LogParams,
+        /// Attempt that failed.
+        attempt: usize,
+        /// Response of the read.
+        result: serde_json::Value,
+    },
+}
+
+impl LogEvent {
+    pub fn request(&self) -> &LogParams {
+        match self {
+            LogEvent::RequestStarted { request, .. }
+            | LogEvent::RetryScheduled { request, .. }
+            | LogEvent::ResponseReceived { request, .. }
+            | LogEvent::PollStatus { request, .. }
+            | LogEvent::Failed { request, .. }
+            | LogEvent::AlreadyPublished { request, .. } => request,
+        }
+    }
+
+    pub(crate) fn request_mut(&mut self) -> &mut LogParams {
+        match self {
+            LogEvent::RequestStarted { request, .. }
+            | LogEvent::RetryScheduled { request, .. }
+            | LogEvent::ResponseReceived { request, .. }
+            | LogEvent::PollStatus { request, .. }
+            | LogEvent::Failed { request, .. }
+            | LogEvent::AlreadyPublished { request, .. } => request,
+        }
+    }
+
+    /// Emit the event through `tracing`, in the span of the API call.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn trace(&self) {
+        match self {
+            LogEvent::RequestStarted { request, attempt } => {
+                tracing::debug!(path = %request.path, attempt, "request started")
+            }
+            LogEvent::RetryScheduled {
+                request,
+                attempt,
+                delay,
+                error,
+            } => tracing::warn!(
+                path = %request.path,
+                attempt,
+                delay_ms = delay.as_millis() as u64,
+                error = %error,
+                "retry scheduled"
+            ),
+            LogEvent::ResponseReceived {
+                request,
+                status,
+                elapsed,
+                usage,
+                ..
+            } => tracing::debug!(
+                path = %request.path,
+                status,
+                elapsed_ms = elapsed.as_millis() as u64,
+                app_call_count = usage.app.as_ref().map(|app| app.call_count),
+                ad_account_util_pct = usage.ad_account.as_ref().map(|ad| ad.acc_id_util_pct),
+                "response received"
+            ),
+            LogEvent::PollStatus {
+                request,
+                phase,
+                status,
+            } => {
+                tracing::debug!(path = %request.path, phase = %phase, status = %status, "poll status")
+            }
+            LogEvent::Failed { request, error } => {
+                tracing::error!(path = %request.path, error = %error, "request failed")
+            }
+            LogEvent::AlreadyPublished {
+                request, attempt, ..
+            } => {
+                tracing::info!(path = %request.path, attempt, "already published")
+            }
+        }
+    }
+}
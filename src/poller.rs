@@ -0,0 +1,316 @@
+use crate::flow::Flow;
+use crate::*;
+
+/// How a status is polled while waiting on Facebook (video processing, Instagram
+/// containers, reel phases): the interval grows from `initial_interval` by `multiplier` up to
+/// `max_interval`, and polling gives up after `max_checks` checks or `max_duration`.
+///
+/// The client default set with [`FbapiBuilder::poll_policy`] applies to every flow; a flow
+/// given its own `poll` uses that one instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PollPolicy {
+    /// Wait after the first check (default 2 seconds).
+    pub initial_interval: Duration,
+    /// Factor applied to the interval after each check (default 1.5).
+    pub multiplier: f64,
+    /// Upper bound of a single interval (default 15 seconds).
+    pub max_interval: Duration,
+    /// Checks including the first one (default 60). With 0 the status is not checked and
+    /// polling fails at once.
+    pub max_checks: usize,
+    /// No check is scheduled later than this after the first one (default 15 minutes).
+    pub max_duration: Option<Duration>,
+}
+
+impl Default for PollPolicy {
+    fn default() -> Self {
+        Self {
+            initial_interval: Duration::from_secs(2),
+            multiplier: 1.5,
+            max_interval: Duration::from_secs(15),
+            max_checks: 60,
+            max_duration: Some(Duration::from_secs(15 * 60)),
+        }
+    }
+}
+
+impl PollPolicy {
+    /// `max_checks` checks, `interval` apart.
+    pub fn fixed(max_checks: usize, interval: Duration) -> Self {
+        Self {
+            initial_interval: interval,
+            multiplier: 1.0,
+            max_interval: interval,
+            max_checks,
+            max_duration: None,
+        }
+    }
+
+    pub fn initial_interval(mut self, initial_interval: Duration) -> Self {
+        self.initial_interval = initial_interval;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    pub fn max_checks(mut self, max_checks: usize) -> Self {
+        self.max_checks = max_checks;
+        self
+    }
+
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Wait after the `check`th check (0 for the first one).
+    pub fn interval(&self, check: usize) -> Duration {
+        let factor = self.multiplier.max(1.0).powi(check.min(32) as i32);
+        let interval = self.initial_interval.as_secs_f64() * factor;
+        Duration::from_secs_f64(interval.min(self.max_interval.as_secs_f64()))
+    }
+
+    /// Whether another check may follow the `check`th one, `elapsed` after the first one.
+    fn should_continue(&self, check: usize, elapsed: Duration, interval: Duration) -> bool {
+        if check + 1 >= self.max_checks {
+            return false;
+        }
+        match self.max_duration {
+            Some(max_duration) => elapsed + interval < max_duration,
+            None => true,
+        }
+    }
+}
+
+/// Where a polled status stands.
+pub(crate) enum PollState {
+    Ready,
+    Pending,
+    Failed(FbapiError),
+}
+
+/// One check of a [`Poller`], passed to its hook.
+pub(crate) struct PollTick<'a> {
+    pub(crate) phase: &'a str,
+    pub(crate) status: &'a str,
+}
+
+/// Repeats a status request until its extractor reports the status ready or failed,
+/// waiting according to a [`PollPolicy`] and within the limits of the [`Flow`].
+pub(crate) struct Poller<'a> {
+    api: &'a Fbapi,
+    request: &'a GraphRequest,
+    phase: &'static str,
+    policy: PollPolicy,
+    retry: Option<&'a RetryPolicy>,
+    flow: &'a Flow,
+    percent: fn(&serde_json::Value) -> Option<u8>,
+    exhausted: fn(&str, Option<String>) -> FbapiError,
+}
+
+impl<'a> Poller<'a> {
+    pub(crate) fn new(
+        api: &'a Fbapi,
+        request: &'a GraphRequest,
+        phase: &'static str,
+        policy: PollPolicy,
+        flow: &'a Flow,
+    ) -> Self {
+        Self {
+            api,
+            request,
+            phase,
+            policy,
+            retry: Some(&NO_RETRY),
+            flow,
+            percent: |_| None,
+            exhausted: |phase, last_status| FbapiError::Timeout {
+                phase: phase.to_owned(),
+                last_status,
+            },
+        }
+    }
+
+    /// Retry policy of each status request (default: a single attempt).
+    pub(crate) fn retry(mut self, retry: Option<&'a RetryPolicy>) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Completion in percent reported to the flow progress, read from a response
+    /// (default: none).
+    pub(crate) fn percent(mut self, percent: fn(&serde_json::Value) -> Option<u8>) -> Self {
+        self.percent = percent;
+        self
+    }
+
+    /// Error returned once the policy allows no more checks
+    /// (default `FbapiError::Timeout`).
+    pub(crate) fn exhausted(mut self, exhausted: fn(&str, Option<String>) -> FbapiError) -> Self {
+        self.exhausted = exhausted;
+        self
+    }
+
+    /// Poll until `extract` maps a response to `Ready` (returning that response) or
+    /// `Failed`. `extract` also returns the status as text for `on_tick` and errors.
+    pub(crate) async fn run(
+        &self,
+        mut extract: impl FnMut(&serde_json::Value, Duration) -> (String, PollState),
+        mut on_tick: impl FnMut(&PollTick),
+        log: &impl Fn(LogEvent),
+    ) -> Result<serde_json::Value, FbapiError> {
+        if self.policy.max_checks == 0 {
+            return Err((self.exhausted)(self.phase, None));
+        }
+        let started = Instant::now();
+        let mut last_status: Option<String> = None;
+        let mut check = 0;
+        loop {
+            let res = self
+                .flow
+                .run(
+                    self.phase,
+                    last_status.as_deref(),
+                    self.api.execute(self.request, self.retry, log),
+                )
+                .await?;
+            let elapsed = started.elapsed();
+            let (status, state) = extract(&res, elapsed);
+            on_tick(&PollTick {
+                phase: self.phase,
+                status: &status,
+            });
+            self.flow.report(self.phase, &status, (self.percent)(&res));
+            match state {
+                PollState::Ready => return Ok(res),
+                PollState::Failed(error) => return Err(error),
+                PollState::Pending => {}
+            }
+            let interval = self.policy.interval(check);
+            last_status = Some(status);
+            if !self.policy.should_continue(check, elapsed, interval) {
+                return Err((self.exhausted)(self.phase, last_status));
+            }
+            self.flow
+                .sleep(interval, self.phase, last_status.as_deref())
+                .await?;
+            check += 1;
+        }
+    }
+}
+
+/// `processing_progress` of a video or reel status.
+pub(crate) fn processing_progress(res: &serde_json::Value) -> Option<u8> {
+    res["status"]["processing_progress"]
+        .as_u64()
+        .map(|percent| percent.min(100) as u8)
+}
+
+static NO_RETRY: Lazy<RetryPolicy> = Lazy::new(RetryPolicy::no_retry);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_interval() {
+        let policy = PollPolicy::default();
+        assert_eq!(policy.interval(0), Duration::from_secs(2));
+        assert_eq!(policy.interval(1), Duration::from_secs(3));
+        assert_eq!(policy.interval(10), Duration::from_secs(15));
+
+        let fixed = PollPolicy::fixed(3, Duration::from_secs(5));
+        assert_eq!(fixed.interval(2), Duration::from_secs(5));
+        assert!(fixed.should_continue(1, Duration::from_secs(3600), fixed.interval(1)));
+        assert!(!fixed.should_continue(2, Duration::from_secs(0), fixed.interval(2)));
+        assert!(!policy.should_continue(0, Duration::from_secs(15 * 60 - 1), policy.interval(0)));
+    }
+
+    #[tokio::test]
+    async fn test_run_stuck_status() {
+        let api = Fbapi::builder("v19.0")
+            .fault_injection(FaultInjection::new().rule(FaultRule::new(Fault::StuckStatus)))
+            .build()
+            .unwrap();
+        let request = GraphRequest::get(api.make_path("123"))
+            .api("check_ig_media")
+            .auth(&Credentials::new("EAAtoken"));
+        let reports = Arc::new(std::sync::Mutex::new(vec![]));
+        let progress = reports.clone();
+        let flow = api.start_flow(Some(
+            &FlowOptions::new().progress(move |p| progress.lock().unwrap().push(p)),
+        ));
+        let mut ticks = vec![];
+        let res = Poller::new(
+            &api,
+            &request,
+            "ig_container",
+            PollPolicy::fixed(3, Duration::from_millis(1)),
+            &flow,
+        )
+        .run(
+            |res, _| {
+                let status = res["status_code"].as_str().unwrap_or_default().to_owned();
+                (status, PollState::Pending)
+            },
+            |tick| ticks.push(tick.status.to_owned()),
+            &|_| {},
+        )
+        .await;
+        match res {
+            Err(FbapiError::Timeout { phase, last_status }) => {
+                assert_eq!(phase, "ig_container");
+                assert_eq!(last_status.as_deref(), Some("IN_PROGRESS"));
+            }
+            other => panic!("Expected Timeout: {:?}", other),
+        }
+        assert_eq!(ticks.len(), 3);
+        assert_eq!(ticks[2], "IN_PROGRESS");
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].phase, "ig_container");
+        assert_eq!(reports[0].percent, None);
+        assert_eq!(
+            processing_progress(&json!({"status": {"processing_progress": 42}})),
+            Some(42)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_without_checks() {
+        let api = Fbapi::new("v19.0", 10, false).unwrap();
+        let request = GraphRequest::get(api.make_path("123")).api("check_video");
+        let flow = api.start_flow(None);
+        let mut ticks = 0;
+        let res = Poller::new(
+            &api,
+            &request,
+            "video_processing",
+            PollPolicy::fixed(0, Duration::from_millis(1)),
+            &flow,
+        )
+        .run(
+            |_, _| ("".to_owned(), PollState::Ready),
+            |_| ticks += 1,
+            &|_| panic!("no request expected"),
+        )
+        .await;
+        assert!(matches!(
+            res,
+            Err(FbapiError::Timeout {
+                last_status: None,
+                ..
+            })
+        ));
+        assert_eq!(ticks, 0);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What is being published, with the ids needed to pick the flow up again.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "platform", rename_all = "snake_case")]
+pub enum PublishTarget {
+    /// Page video returned by `upload_video`, published with `publish_video`.
+    FacebookVideo {
+        page_id: String,
+        video_id: String,
+        #[serde(default)]
+        via_videos_tab: bool,
+    },
+    /// Page reel returned by `upload_video_reel`, published with `publish_video_reel`.
+    FacebookReel {
+        page_id: String,
+        video_id: String,
+        #[serde(default)]
+        description: String,
+    },
+    /// Instagram container returned by an `upload_ig_*` call, published with
+    /// `post_ig_media_publish`.
+    Instagram {
+        account_id: String,
+        creation_id: String,
+    },
+}
+
+impl PublishTarget {
+    /// Id of the uploaded video or container.
+    pub fn id(&self) -> &str {
+        match self {
+            PublishTarget::FacebookVideo { video_id, .. } => video_id,
+            PublishTarget::FacebookReel { video_id, .. } => video_id,
+            PublishTarget::Instagram { creation_id, .. } => creation_id,
+        }
+    }
+}
+
+/// Chunked upload of `upload_video_file` that has not been finished: the session and the
+/// range Facebook expects next. Pass it to `resume_video_file` to continue the transfer.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct UploadSession {
+    pub video_id: String,
+    pub upload_session_id: String,
+    pub start_offset: u64,
+    pub end_offset: u64,
+}
+
+/// Phase a publish reached, in order.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishPhase {
+    /// Uploaded; Facebook may still be processing the media.
+    Processing,
+    /// Processed and waiting to be published.
+    Ready,
+    /// The publish request was sent, so it may have been accepted.
+    Publishing,
+    /// Published.
+    Published,
+}
+
+/// Serializable state of a publish, to be stored and passed to [`Fbapi::resume`] after a
+/// crash or a deploy. Timestamps are unix seconds.
+///
+/// Create the handle once the upload returned its id, and keep it up to date with
+/// [`PublishHandle::record`] from the progress callback of the flow. For a chunked upload,
+/// the handle can be created from the `video_id` of the first `transfer` report, so that
+/// `upload` keeps the session until the upload is finished.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PublishHandle {
+    #[serde(flatten)]
+    pub target: PublishTarget,
+    pub phase: PublishPhase,
+    /// Chunked upload still in progress.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload: Option<UploadSession>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl PublishHandle {
+    /// Handle in the `Processing` phase.
+    pub fn new(target: PublishTarget) -> Self {
+        let now = unix_now();
+        Self {
+            target,
+            phase: PublishPhase::Processing,
+            upload: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn facebook_video(page_id: &str, video_id: &str, via_videos_tab: bool) -> Self {
+        Self::new(PublishTarget::FacebookVideo {
+            page_id: page_id.to_owned(),
+            video_id: video_id.to_owned(),
+            via_videos_tab,
+        })
+    }
+
+    pub fn facebook_reel(page_id: &str, video_id: &str, description: &str) -> Self {
+        Self::new(PublishTarget::FacebookReel {
+            page_id: page_id.to_owned(),
+            video_id: video_id.to_owned(),
+            description: description.to_owned(),
+        })
+    }
+
+    pub fn instagram(account_id: &str, creation_id: &str) -> Self {
+        Self::new(PublishTarget::Instagram {
+            account_id: account_id.to_owned(),
+            creation_id: creation_id.to_owned(),
+        })
+    }
+
+    /// Move to `phase` and touch `updated_at`.
+    pub fn set_phase(&mut self, phase: PublishPhase) {
+        self.phase = phase;
+        self.updated_at = unix_now();
+    }
+
+    /// Advance the phase from a progress report of the flow; a report never moves the
+    /// handle back to an earlier phase. The session of a chunked upload is kept until the
+    /// upload is finished.
+    pub fn record(&mut self, progress: &PublishProgress) {
+        match (progress.phase.as_str(), progress.status.as_str()) {
+            ("upload", "transfer") if progress.upload.is_some() => {
+                self.upload = progress.upload.clone();
+                self.updated_at = unix_now();
+            }
+            ("upload", "finished") => {
+                self.upload = None;
+                self.updated_at = unix_now();
+            }
+            _ => {}
+        }
+        let phase = match (progress.phase.as_str(), progress.status.as_str()) {
+            ("video_processing", "ready")
+            | ("ig_container", "FINISHED")
+            | ("reel_copyright_check", "complete") => PublishPhase::Ready,
+            ("publish", _) | ("reel_processing", _) => PublishPhase::Publishing,
+            ("reel_publishing", "complete") => PublishPhase::Published,
+            ("reel_publishing", _) => PublishPhase::Publishing,
+            _ => PublishPhase::Processing,
+        };
+        if phase > self.phase {
+            self.set_phase(phase);
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(phase: &str, status: &str) -> PublishProgress {
+        PublishProgress {
+            phase: phase.to_owned(),
+            status: status.to_owned(),
+            percent: None,
+            elapsed: Duration::from_secs(1),
+            upload: None,
+        }
+    }
+
+    #[test]
+    fn test_serde_and_record() {
+        let mut handle = PublishHandle::instagram("17841400000000000", "18000000000000000");
+        let value = serde_json::to_value(&handle).unwrap();
+        assert_eq!(value["platform"], "instagram");
+        assert_eq!(value["creation_id"], "18000000000000000");
+        assert_eq!(value["phase"], "processing");
+        let parsed: PublishHandle = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed, handle);
+
+        handle.record(&progress("ig_container", "IN_PROGRESS"));
+        assert_eq!(handle.phase, PublishPhase::Processing);
+        handle.record(&progress("ig_container", "FINISHED"));
+        assert_eq!(handle.phase, PublishPhase::Ready);
+        handle.record(&progress("publish", "started"));
+        assert_eq!(handle.phase, PublishPhase::Publishing);
+        handle.record(&progress("upload", "started"));
+        assert_eq!(handle.phase, PublishPhase::Publishing);
+
+        let mut reel = PublishHandle::facebook_reel("123", "456", "hello");
+        reel.record(&progress("reel_uploading", "complete"));
+        assert_eq!(reel.phase, PublishPhase::Processing);
+        reel.record(&progress("reel_copyright_check", "complete"));
+        assert_eq!(reel.phase, PublishPhase::Ready);
+        reel.record(&progress("thumbnail", "started"));
+        assert_eq!(reel.phase, PublishPhase::Ready);
+        reel.record(&progress("reel_publishing", "complete"));
+        assert_eq!(reel.phase, PublishPhase::Published);
+        assert_eq!(reel.target.id(), "456");
+    }
+
+    #[test]
+    fn test_record_upload() {
+        let session = UploadSession {
+            video_id: "456".to_owned(),
+            upload_session_id: "789".to_owned(),
+            start_offset: 1048576,
+            end_offset: 2097152,
+        };
+        let mut handle = PublishHandle::facebook_video("123", "456", false);
+        handle.record(&PublishProgress {
+            upload: Some(session.clone()),
+            ..progress("upload", "transfer")
+        });
+        assert_eq!(handle.upload, Some(session));
+        let value = serde_json::to_value(&handle).unwrap();
+        assert_eq!(value["upload"]["upload_session_id"], "789");
+        assert_eq!(value["upload"]["start_offset"], 1048576);
+        assert_eq!(
+            serde_json::from_value::<PublishHandle>(value).unwrap(),
+            handle
+        );
+
+        handle.record(&progress("upload", "finished"));
+        assert_eq!(handle.upload, None);
+        assert_eq!(handle.phase, PublishPhase::Processing);
+        assert!(serde_json::to_value(&handle)
+            .unwrap()
+            .get("upload")
+            .is_none());
+    }
+}
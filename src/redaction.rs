@@ -0,0 +1,241 @@
+use crate::*;
+
+const MASK: &str = "***";
+
+/// Parameters masked by [`Redaction::default`].
+pub const DEFAULT_SENSITIVE_KEYS: [&str; 7] = [
+    "access_token",
+    "appsecret_proof",
+    "client_secret",
+    "fb_exchange_token",
+    "input_token",
+    "upload_session_id",
+    "code",
+];
+
+/// Masks secrets in a [`LogEvent`] before it reaches the `log` callback.
+///
+/// Values of sensitive keys are replaced in the request `params`, in the query string of
+/// its `path`, in the response `result` and in error messages. Values that look like
+/// Facebook access tokens are masked whatever their key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redaction {
+    keys: Vec<String>,
+    enabled: bool,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self {
+            keys: DEFAULT_SENSITIVE_KEYS
+                .iter()
+                .map(|key| (*key).to_owned())
+                .collect(),
+            enabled: true,
+        }
+    }
+}
+
+impl Redaction {
+    /// Redaction of `keys` only.
+    pub fn new(keys: &[&str]) -> Self {
+        Self {
+            keys: keys.iter().map(|key| (*key).to_owned()).collect(),
+            enabled: true,
+        }
+    }
+
+    /// No redaction at all, for local debugging.
+    pub fn disabled() -> Self {
+        Self {
+            keys: vec![],
+            enabled: false,
+        }
+    }
+
+    /// Also mask `key`.
+    pub fn key(mut self, key: &str) -> Self {
+        self.keys.push(key.to_owned());
+        self
+    }
+
+    fn is_sensitive(&self, key: &str, value: &str) -> bool {
+        self.keys.iter().any(|k| k.eq_ignore_ascii_case(key)) || looks_like_token(value)
+    }
+
+    pub fn apply(&self, mut event: LogEvent) -> LogEvent {
+        if !self.enabled {
+            return event;
+        }
+        self.apply_params(event.request_mut());
+        match &mut event {
+            LogEvent::ResponseReceived { result, .. }
+            | LogEvent::AlreadyPublished { result, .. } => self.apply_json(result),
+            LogEvent::RetryScheduled { error, .. } | LogEvent::Failed { error, .. } => {
+                *error = self.apply_text(error)
+            }
+            _ => {}
+        }
+        event
+    }
+
+    fn apply_params(&self, params: &mut LogParams) {
+        for (key, value) in params.params.iter_mut() {
+            if self.is_sensitive(key, value) {
+                *value = MASK.to_owned();
+            }
+        }
+        params.path = self.apply_url(&params.path);
+    }
+
+    /// Error messages may quote a url (reqwest errors do) or a token.
+    fn apply_text(&self, text: &str) -> String {
+        let mut masked = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(end) = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_') {
+            let (word, tail) = rest.split_at(end);
+            masked.push_str(&self.mask_word(word));
+            let mut chars = tail.chars();
+            let separator = chars.next().unwrap_or_default();
+            masked.push(separator);
+            rest = chars.as_str();
+            if separator == '=' && self.keys.iter().any(|k| k.eq_ignore_ascii_case(word)) {
+                let end = rest
+                    .find(|c: char| c == '&' || c == '"' || c == ')' || c.is_whitespace())
+                    .unwrap_or(rest.len());
+                masked.push_str(MASK);
+                rest = &rest[end..];
+            }
+        }
+        masked.push_str(&self.mask_word(rest));
+        masked
+    }
+
+    fn mask_word(&self, word: &str) -> String {
+        if looks_like_token(word) {
+            MASK.to_owned()
+        } else {
+            word.to_owned()
+        }
+    }
+
+    fn apply_url(&self, url: &str) -> String {
+        let (base, query) = match url.split_once('?') {
+            Some(split) => split,
+            None => return url.to_owned(),
+        };
+        let pairs = url::form_urlencoded::parse(query.as_bytes()).map(|(key, value)| {
+            if self.is_sensitive(&key, &value) {
+                (key, MASK.into())
+            } else {
+                (key, value)
+            }
+        });
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs)
+            .finish();
+        format!("{}?{}", base, query)
+    }
+
+    fn apply_json(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    match value {
+                        serde_json::Value::String(s) if self.is_sensitive(key, s) => {
+                            *s = MASK.to_owned();
+                        }
+                        _ => self.apply_json(value),
+                    }
+                }
+            }
+            serde_json::Value::Array(list) => list.iter_mut().for_each(|v| self.apply_json(v)),
+            _ => {}
+        }
+    }
+}
+
+/// Facebook user, page and app tokens start with `EAA` and are long.
+fn looks_like_token(value: &str) -> bool {
+    value.starts_with("EAA")
+        && value.len() >= 32
+        && value.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "EAAGm0PX4ZCpsBAO2ZBdfghjklZA0123456789";
+
+    #[test]
+    fn test_apply() {
+        let request = LogParams::new(
+            &format!(
+                "https://graph.facebook.com/v19.0/me?fields=id&access_token={}&appsecret_proof=abc",
+                TOKEN
+            ),
+            &[
+                ("message", "hello"),
+                ("page_token", TOKEN),
+                ("my_secret", "x"),
+            ],
+        );
+        let event = Redaction::default()
+            .key("my_secret")
+            .apply(LogEvent::ResponseReceived {
+                request,
+                status: 200,
+                elapsed: Duration::from_millis(10),
+                usage: Usage::default(),
+                result: json!({"data": [{"id": "1", "access_token": "short"}]}),
+            });
+        assert_eq!(
+            event.request().path,
+            "https://graph.facebook.com/v19.0/me?fields=id&access_token=***&appsecret_proof=***"
+        );
+        assert_eq!(
+            event.request().params,
+            vec![
+                ("message".to_owned(), "hello".to_owned()),
+                ("page_token".to_owned(), "***".to_owned()),
+                ("my_secret".to_owned(), "***".to_owned()),
+            ]
+        );
+        match event {
+            LogEvent::ResponseReceived { result, .. } => assert_eq!(
+                result,
+                json!({"data": [{"id": "1", "access_token": "***"}]})
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_apply_error() {
+        let event = Redaction::default().apply(LogEvent::Failed {
+            request: LogParams::new("https://graph.facebook.com/v19.0/me", &[]),
+            error: format!(
+                "error sending request for url (https://graph.facebook.com/v19.0/me?access_token=abc&fields=id): timed out, token {}",
+                TOKEN
+            ),
+        });
+        match event {
+            LogEvent::Failed { error, .. } => assert_eq!(
+                error,
+                "error sending request for url (https://graph.facebook.com/v19.0/me?access_token=***&fields=id): timed out, token ***"
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_disabled() {
+        let path = format!("https://graph.facebook.com/v19.0/me?access_token={}", TOKEN);
+        let event = Redaction::disabled().apply(LogEvent::RequestStarted {
+            request: LogParams::new(&path, &[]),
+            attempt: 0,
+        });
+        assert_eq!(event.request().path, path);
+    }
+}
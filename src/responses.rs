@@ -0,0 +1,367 @@
+use crate::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Token returned by `oauth/access_token`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AccessToken {
+    pub access_token: String,
+    #[serde(default)]
+    pub token_type: Option<String>,
+    /// Seconds until the token expires, absent for tokens that do not expire.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+}
+
+/// Page returned by `/{user-id}/accounts` together with its page access token. The edge
+/// does not report when the token expires; read it with `debug_token` (`expires_at`) when
+/// needed. Page tokens derived from a long-lived user token usually do not expire.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PageToken {
+    pub id: String,
+    pub name: String,
+    pub access_token: String,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tasks: Vec<String>,
+}
+
+/// Result of `/debug_token`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TokenInfo {
+    #[serde(default)]
+    pub app_id: Option<String>,
+    /// `USER`, `PAGE`, `APP` ...
+    #[serde(rename = "type", default)]
+    pub token_type: Option<String>,
+    #[serde(default)]
+    pub application: Option<String>,
+    pub is_valid: bool,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub granular_scopes: Vec<GranularScope>,
+    /// Unix time, 0 when the token never expires.
+    #[serde(default)]
+    pub expires_at: u64,
+    /// Unix time until which the token can access user data.
+    #[serde(default)]
+    pub data_access_expires_at: u64,
+    #[serde(default)]
+    pub issued_at: Option<u64>,
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub profile_id: Option<String>,
+    #[serde(default)]
+    pub error: Option<TokenError>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GranularScope {
+    pub scope: String,
+    #[serde(default)]
+    pub target_ids: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TokenError {
+    pub code: u64,
+    #[serde(default)]
+    pub subcode: Option<u64>,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl TokenInfo {
+    /// True when the token is invalid or expires (or loses data access) within `duration`.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.expires_within_at(now, duration)
+    }
+
+    fn expires_within_at(&self, now: u64, duration: Duration) -> bool {
+        let limit = now + duration.as_secs();
+        !self.is_valid
+            || (self.expires_at != 0 && self.expires_at <= limit)
+            || (self.data_access_expires_at != 0 && self.data_access_expires_at <= limit)
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+/// Result of publishing a video to a page.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PublishedPost {
+    /// `id` as returned by Facebook; `None` when the response has none. It is not filled
+    /// in with the video id, which is a different object from the post.
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub post_id: Option<String>,
+    #[serde(skip)]
+    raw: serde_json::Value,
+    #[serde(skip)]
+    usage: Usage,
+}
+
+/// Result of `/{ig-user-id}/media_publish`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct IgMediaPublished {
+    pub id: String,
+    #[serde(skip)]
+    raw: serde_json::Value,
+    #[serde(skip)]
+    usage: Usage,
+}
+
+/// Result of `/{page-id}/photos` and `/{album-id}/photos`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PhotoUpload {
+    pub id: String,
+    #[serde(default)]
+    pub post_id: Option<String>,
+    #[serde(skip)]
+    raw: serde_json::Value,
+    #[serde(skip)]
+    usage: Usage,
+}
+
+/// Result of `/{page-id}/albums`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct AlbumCreated {
+    pub id: String,
+    #[serde(skip)]
+    raw: serde_json::Value,
+    #[serde(skip)]
+    usage: Usage,
+}
+
+/// Result of publishing a video reel.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ReelPublished {
+    pub post_id: String,
+    /// Id of the published video, taken from the request since the finish response does
+    /// not return it; it is not the post id.
+    #[serde(default)]
+    pub video_id: String,
+    #[serde(skip)]
+    raw: serde_json::Value,
+    #[serde(skip)]
+    usage: Usage,
+}
+
+/// Result of a publishing call that must not be repeated blindly
+/// (`post_ig_media_publish`, `publish_video`, `post_feed_array`).
+#[derive(Clone, Debug, PartialEq)]
+pub enum PublishOutcome<T> {
+    /// Published by this call.
+    Published(T),
+    /// Facebook already had the content: a check before a retry found the earlier attempt
+    /// published, or the call failed as a duplicate (code 506). Holds the response of the
+    /// check or the duplicate error.
+    AlreadyPublished(serde_json::Value),
+}
+
+impl<T> PublishOutcome<T> {
+    pub fn published(&self) -> Option<&T> {
+        match self {
+            PublishOutcome::Published(value) => Some(value),
+            PublishOutcome::AlreadyPublished(_) => None,
+        }
+    }
+
+    pub fn is_already_published(&self) -> bool {
+        matches!(self, PublishOutcome::AlreadyPublished(_))
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> PublishOutcome<U> {
+        match self {
+            PublishOutcome::Published(value) => PublishOutcome::Published(f(value)),
+            PublishOutcome::AlreadyPublished(response) => {
+                PublishOutcome::AlreadyPublished(response)
+            }
+        }
+    }
+
+    pub(crate) fn from_result(result: Result<T, FbapiError>) -> Result<Self, FbapiError> {
+        match result {
+            Ok(value) => Ok(PublishOutcome::Published(value)),
+            Err(FbapiError::AlreadyPublished(response)) => {
+                Ok(PublishOutcome::AlreadyPublished(response))
+            }
+            Err(FbapiError::Facebook(response)) if response["error"]["code"] == 506 => {
+                Ok(PublishOutcome::AlreadyPublished(response))
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// Result of `Fbapi::resume`, by platform.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PublishedMedia {
+    Video(PublishedPost),
+    Reel(ReelPublished),
+    Instagram(IgMediaPublished),
+}
+
+/// Typed response that keeps the original json and the usage headers.
+pub(crate) trait RawResponse: DeserializeOwned {
+    fn set_raw(&mut self, raw: serde_json::Value, usage: Usage);
+}
+
+macro_rules! impl_raw_response {
+    ($($name:ident),*) => {
+        $(
+            impl $name {
+                /// Response as returned by the Graph API.
+                pub fn raw(&self) -> &serde_json::Value {
+                    &self.raw
+                }
+
+                /// Rate limit usage reported with the response.
+                pub fn usage(&self) -> &Usage {
+                    &self.usage
+                }
+            }
+
+            impl RawResponse for $name {
+                fn set_raw(&mut self, raw: serde_json::Value, usage: Usage) {
+                    self.raw = raw;
+                    self.usage = usage;
+                }
+            }
+        )*
+    };
+}
+
+impl_raw_response!(
+    PublishedPost,
+    IgMediaPublished,
+    PhotoUpload,
+    AlbumCreated,
+    ReelPublished
+);
+
+pub(crate) fn parse_raw<T: RawResponse>(
+    res: serde_json::Value,
+    usage: Usage,
+) -> Result<T, FbapiError> {
+    let mut value: T = parse(res.clone())?;
+    value.set_raw(res, usage);
+    Ok(value)
+}
+
+/// Deserialize into a caller provided type, reporting the path of the field that failed.
+pub(crate) fn deserialize<T: DeserializeOwned>(res: serde_json::Value) -> Result<T, FbapiError> {
+    serde_path_to_error::deserialize(&res).map_err(|e| FbapiError::Deserialize {
+        path: e.path().to_string(),
+        source: e.into_inner(),
+        response: res.clone(),
+    })
+}
+
+pub(crate) fn parse<T: DeserializeOwned>(res: serde_json::Value) -> Result<T, FbapiError> {
+    T::deserialize(&res).map_err(|_| FbapiError::UnExpected(res))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_page_tokens() {
+        let res = json!([
+            {
+                "id": "1234",
+                "name": "My Page",
+                "access_token": "EAApage",
+                "category": "Software",
+                "tasks": ["ANALYZE", "CREATE_CONTENT"]
+            },
+            {
+                "id": "5678",
+                "name": "Other Page",
+                "access_token": "EAAother"
+            }
+        ]);
+        let pages: Vec<PageToken> = parse(res).unwrap();
+        assert_eq!(pages[0].tasks, vec!["ANALYZE", "CREATE_CONTENT"]);
+        assert_eq!(pages[1].category, None);
+        assert!(pages[1].tasks.is_empty());
+    }
+
+    #[test]
+    fn test_parse_token_info() {
+        let res = json!({
+            "app_id": "138483919580948",
+            "type": "USER",
+            "application": "Social Cafe",
+            "data_access_expires_at": 1700000000,
+            "expires_at": 1600000000,
+            "is_valid": true,
+            "scopes": ["pages_show_list", "pages_manage_posts"],
+            "granular_scopes": [
+                {"scope": "pages_show_list"},
+                {"scope": "pages_manage_posts", "target_ids": ["1234"]}
+            ],
+            "user_id": "1207059"
+        });
+        let info: TokenInfo = parse(res).unwrap();
+        assert_eq!(info.token_type.as_deref(), Some("USER"));
+        assert!(info.has_scope("pages_manage_posts"));
+        assert_eq!(info.granular_scopes[1].target_ids, vec!["1234"]);
+        assert!(!info.expires_within_at(1500000000, Duration::from_secs(3600)));
+        assert!(info.expires_within_at(1599999000, Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_parse_raw() {
+        let res = json!({"id": "17895695668004550", "extra": 1});
+        let usage = Usage {
+            app: Some(AppUsage::default()),
+            ..Usage::default()
+        };
+        let published: IgMediaPublished = parse_raw(res.clone(), usage.clone()).unwrap();
+        assert_eq!(published.id, "17895695668004550");
+        assert_eq!(published.raw(), &res);
+        assert_eq!(published.usage(), &usage);
+    }
+
+    #[test]
+    fn test_deserialize_reports_path() {
+        #[derive(Deserialize, Debug)]
+        struct Post {
+            #[allow(dead_code)]
+            id: String,
+        }
+        #[derive(Deserialize, Debug)]
+        struct Posts {
+            #[allow(dead_code)]
+            data: Vec<Post>,
+        }
+        let res = json!({"data": [{"id": "1"}, {"id": 2}]});
+        match deserialize::<Posts>(res.clone()) {
+            Err(FbapiError::Deserialize { path, response, .. }) => {
+                assert_eq!(path, "data[1].id");
+                assert_eq!(response, res);
+            }
+            other => panic!("Expected Deserialize error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_unexpected() {
+        let res = json!({"access_token": 1});
+        match parse::<AccessToken>(res.clone()) {
+            Err(FbapiError::UnExpected(value)) => assert_eq!(value, res),
+            _ => panic!("Expected UnExpected error"),
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use crate::error::FbapiError;
+use rand::Rng;
+use std::time::Duration;
+
+/// How a failed request is retried: exponential backoff with jitter, bounded by a number
+/// of attempts and an optional overall deadline.
+///
+/// The client default is set with [`FbapiBuilder::retry_policy`](crate::FbapiBuilder::retry_policy);
+/// every API takes an `Option<&RetryPolicy>` to override it for one call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Delay before the first retry (default 500ms).
+    pub initial_delay: Duration,
+    /// Factor applied to the delay after each retry (default 2.0).
+    pub multiplier: f64,
+    /// Upper bound of a single delay (default 30s).
+    pub max_delay: Duration,
+    /// Random variation of each delay, as a fraction of it: 0.2 gives ±20% (default 0.2).
+    pub jitter: f64,
+    /// Attempts including the first one (default 3). 0 and 1 both mean a single attempt.
+    pub max_attempts: usize,
+    /// No retry is scheduled once it would start later than this after the first attempt
+    /// (default none).
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+            max_attempts: 3,
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A single attempt.
+    pub fn no_retry() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Delay before retrying after the failed `attempt` (0 for the first one), without jitter.
+    pub fn base_delay(&self, attempt: usize) -> Duration {
+        let factor = self.multiplier.max(1.0).powi(attempt.min(32) as i32);
+        let delay = self.initial_delay.as_secs_f64() * factor;
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return self.base_delay(attempt);
+        }
+        let factor = 1.0 + rand::thread_rng().gen_range(-jitter..=jitter);
+        self.base_delay(attempt).mul_f64(factor).min(self.max_delay)
+    }
+
+    /// Whether another attempt may follow the failed `attempt`, starting after `delay`
+    /// when `elapsed` has passed since the first one.
+    pub(crate) fn should_retry(&self, attempt: usize, elapsed: Duration, delay: Duration) -> bool {
+        if attempt + 1 >= self.max_attempts {
+            return false;
+        }
+        match self.deadline {
+            Some(deadline) => elapsed + delay < deadline,
+            None => true,
+        }
+    }
+}
+
+/// Decides whether a failed attempt is worth retrying.
+///
+/// `status` is the HTTP status when a response was received, `None` for transport errors.
+/// Whether a retry actually happens also depends on the [`RetryPolicy`].
+pub trait RetryClassifier: Send + Sync {
+    fn is_retryable(&self, status: Option<u16>, error: &FbapiError) -> bool;
+
+    /// Same question for a request that can be sent again even when the failed attempt
+    /// reached Facebook: a GET, or a request whose published check runs before each retry.
+    /// Defaults to `is_retryable`.
+    fn is_retryable_idempotent(&self, status: Option<u16>, error: &FbapiError) -> bool {
+        self.is_retryable(status, error)
+    }
+}
+
+/// Retries connection errors, 5xx responses without a Graph API error body, and Graph API
+/// errors that Facebook marks `is_transient` or that have code 1 (unknown), 2 (service
+/// unavailable) or 4 (app rate limit). Other transport errors, such as a timeout, and a
+/// response body that could not be read or decoded are retried only by
+/// `is_retryable_idempotent`, since Facebook may already have accepted the request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultRetryClassifier;
+
+/// Graph API codes worth retrying after a delay.
+const TRANSIENT_CODES: [u64; 3] = [1, 2, 4];
+
+impl RetryClassifier for DefaultRetryClassifier {
+    fn is_retryable(&self, status: Option<u16>, error: &FbapiError) -> bool {
+        match error {
+            FbapiError::Facebook(value) => {
+                value["error"]["is_transient"].as_bool() == Some(true)
+                    || matches!(error.error_code(), Some(code) if TRANSIENT_CODES.contains(&code))
+            }
+            FbapiError::Throttled { .. } => false,
+            // Body that is not json: an html error page of a proxy or a truncated body.
+            _ => match status {
+                Some(status) => status >= 500,
+                None => error.is_not_sent(),
+            },
+        }
+    }
+
+    fn is_retryable_idempotent(&self, status: Option<u16>, error: &FbapiError) -> bool {
+        match error {
+            FbapiError::Facebook(_) | FbapiError::Throttled { .. } => {
+                self.is_retryable(status, error)
+            }
+            FbapiError::Reqwest(e) if e.is_timeout() || e.is_body() || e.is_decode() => true,
+            _ => status.is_none_or(|status| status >= 500),
+        }
+    }
+}
+
+impl From<usize> for RetryPolicy {
+    /// Default backoff with `max_attempts` attempts.
+    fn from(max_attempts: usize) -> Self {
+        Self::default().max_attempts(max_attempts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Credentials, Fault, FaultInjection, FaultRule, Fbapi};
+
+    #[test]
+    fn test_base_delay() {
+        let policy = RetryPolicy::default().max_delay(Duration::from_secs(3));
+        assert_eq!(policy.base_delay(0), Duration::from_millis(500));
+        assert_eq!(policy.base_delay(1), Duration::from_secs(1));
+        assert_eq!(policy.base_delay(2), Duration::from_secs(2));
+        assert_eq!(policy.base_delay(3), Duration::from_secs(3));
+        assert_eq!(policy.base_delay(100), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let policy = RetryPolicy::default();
+        for _ in 0..100 {
+            let delay = policy.delay(1);
+            assert!(delay >= Duration::from_millis(800) && delay <= Duration::from_millis(1200));
+        }
+    }
+
+    #[test]
+    fn test_default_classifier() {
+        let classifier = DefaultRetryClassifier;
+        let error = |body: serde_json::Value| FbapiError::Facebook(json!({ "error": body }));
+        assert!(classifier.is_retryable(Some(500), &error(json!({"code": 2}))));
+        assert!(classifier.is_retryable(Some(400), &error(json!({"code": 4}))));
+        assert!(classifier.is_retryable(
+            Some(400),
+            &error(json!({"code": 368, "is_transient": true}))
+        ));
+        assert!(!classifier.is_retryable(Some(400), &error(json!({"code": 190}))));
+        assert!(!classifier.is_retryable(Some(400), &error(json!({"code": 32}))));
+
+        let malformed = || FbapiError::from(serde_json::from_str::<i32>("<html>").unwrap_err());
+        assert!(classifier.is_retryable(Some(502), &malformed()));
+        assert!(!classifier.is_retryable(Some(200), &malformed()));
+        assert!(classifier.is_retryable(None, &FbapiError::InjectedTimeout));
+
+        let io = || FbapiError::from(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        assert!(!classifier.is_retryable(None, &io()));
+        assert!(classifier.is_retryable_idempotent(None, &io()));
+        assert!(classifier.is_retryable_idempotent(Some(502), &malformed()));
+        assert!(!classifier.is_retryable_idempotent(Some(400), &error(json!({"code": 190}))));
+    }
+
+    #[tokio::test]
+    async fn test_get_retried_after_decode_error() {
+        let api = Fbapi::builder("v19.0")
+            .retry_policy(RetryPolicy::default().initial_delay(Duration::from_millis(1)))
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(FaultRule::new(Fault::MalformedJson).first_calls(1))
+                    .rule(FaultRule::new(Fault::Response {
+                        status: 200,
+                        body: r#"{"id":"1"}"#.to_owned(),
+                    })),
+            )
+            .build()
+            .unwrap();
+        let res = api
+            .get_object(&Credentials::new("EAAtoken"), "1", "", &[], None, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(res["id"], "1");
+
+        let api = Fbapi::builder("v19.0")
+            .retry_policy(RetryPolicy::default().initial_delay(Duration::from_millis(1)))
+            .fault_injection(
+                FaultInjection::new().rule(FaultRule::new(Fault::MalformedJson).first_calls(1)),
+            )
+            .build()
+            .unwrap();
+        let res = api
+            .create_album(&Credentials::new("EAAtoken"), "1", "name", "", None, |_| {})
+            .await;
+        assert!(matches!(res, Err(FbapiError::Reqwest(_))));
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::default().deadline(Duration::from_secs(10));
+        let second = Duration::from_secs(1);
+        assert!(policy.should_retry(0, second, second));
+        assert!(!policy.should_retry(2, second, second));
+        assert!(!policy.should_retry(0, Duration::from_secs(9), second));
+        assert!(!RetryPolicy::no_retry().should_retry(0, second, second));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::*;
+
+/// Client side throttling driven by the usage headers in the [`UsageRegistry`].
+///
+/// Before a request is sent, the highest percentage of the usage of the client's app (see
+/// [`FbapiBuilder::app_id`]) and of the business
+/// use case usage of the requested node (page, Instagram account, ...) is compared with
+/// the thresholds. Between `delay_threshold` and `reject_threshold` the request is delayed
+/// proportionally, up to `max_delay`; from `reject_threshold` on it fails with
+/// `FbapiError::Throttled`. A node whose `estimated_time_to_regain_access` has not passed
+/// yet is waited for when it is within `max_delay`, rejected otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThrottlePolicy {
+    /// Usage percentage from which requests are delayed (default 75).
+    pub delay_threshold: f64,
+    /// Usage percentage from which requests are rejected (default 95).
+    pub reject_threshold: f64,
+    /// Longest delay before a request (default 30 seconds).
+    pub max_delay: Duration,
+    /// Usage reported longer ago than this is ignored (default 5 minutes).
+    pub stale_after: Duration,
+}
+
+impl Default for ThrottlePolicy {
+    fn default() -> Self {
+        Self {
+            delay_threshold: 75.0,
+            reject_threshold: 95.0,
+            max_delay: Duration::from_secs(30),
+            stale_after: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Throttle {
+    Proceed,
+    Delay(Duration),
+    Reject(Option<Duration>),
+}
+
+impl ThrottlePolicy {
+    pub(crate) fn decide(
+        &self,
+        registry: &UsageRegistry,
+        app_id: &str,
+        node: Option<&str>,
+    ) -> Throttle {
+        let mut level: f64 = 0.0;
+        let mut regain = Duration::from_secs(0);
+        if let Some((app, age)) = registry.app_with_age(app_id) {
+            if age < self.stale_after {
+                level = level
+                    .max(app.call_count)
+                    .max(app.total_cputime)
+                    .max(app.total_time);
+            }
+        }
+        if let Some((entries, age)) = node.and_then(|node| registry.business_with_age(node)) {
+            for entry in entries {
+                let wait =
+                    Duration::from_secs(entry.estimated_time_to_regain_access.saturating_mul(60));
+                regain = regain.max(wait.saturating_sub(age));
+                if age < self.stale_after {
+                    level = level
+                        .max(entry.call_count)
+                        .max(entry.total_cputime)
+                        .max(entry.total_time);
+                }
+            }
+        }
+        if regain > self.max_delay {
+            return Throttle::Reject(Some(regain));
+        }
+        if level >= self.reject_threshold {
+            return Throttle::Reject(if regain.is_zero() { None } else { Some(regain) });
+        }
+        let delay = if level >= self.delay_threshold {
+            let ratio = (level - self.delay_threshold)
+                / (self.reject_threshold - self.delay_threshold).max(f64::EPSILON);
+            self.max_delay.mul_f64(ratio.min(1.0))
+        } else {
+            Duration::from_secs(0)
+        };
+        match delay.max(regain) {
+            delay if delay.is_zero() => Throttle::Proceed,
+            delay => Throttle::Delay(delay),
+        }
+    }
+}
+
+impl Fbapi {
+    /// Wait or fail according to the [`ThrottlePolicy`], if one is set.
+    pub(crate) async fn throttle(&self, request: &GraphRequest) -> Result<(), FbapiError> {
+        let policy = match &self.throttle_policy {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+        match policy.decide(
+            &self.usage_registry,
+            &self.app_id,
+            self.node_of(request.base_url()).as_deref(),
+        ) {
+            Throttle::Proceed => Ok(()),
+            Throttle::Delay(delay) => {
+                sleep(delay).await;
+                Ok(())
+            }
+            Throttle::Reject(retry_after) => Err(FbapiError::Throttled { retry_after }),
+        }
+    }
+
+    /// First path segment after the version, e.g. the page id of `{version}/{page-id}/feed`.
+    fn node_of(&self, url: &str) -> Option<String> {
+        let url = url::Url::parse(url).ok()?;
+        let mut segments = url.path_segments()?;
+        segments.find(|segment| *segment == self.version)?;
+        segments
+            .next()
+            .filter(|node| !node.is_empty())
+            .map(|node| node.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(app_call_count: f64, page: BucUsage) -> UsageRegistry {
+        let registry = UsageRegistry::new();
+        let mut usage = Usage {
+            app: Some(AppUsage {
+                call_count: app_call_count,
+                ..AppUsage::default()
+            }),
+            ..Usage::default()
+        };
+        usage.business.insert("123".to_owned(), vec![page]);
+        registry.update("app", &usage);
+        registry
+    }
+
+    #[test]
+    fn test_decide() {
+        let policy = ThrottlePolicy::default();
+        let low = registry(10.0, BucUsage::default());
+        assert_eq!(policy.decide(&low, "app", Some("123")), Throttle::Proceed);
+
+        let busy_page = registry(
+            10.0,
+            BucUsage {
+                call_count: 85.0,
+                ..BucUsage::default()
+            },
+        );
+        assert_eq!(
+            policy.decide(&busy_page, "app", Some("123")),
+            Throttle::Delay(Duration::from_secs(15))
+        );
+        assert_eq!(
+            policy.decide(&busy_page, "app", Some("456")),
+            Throttle::Proceed
+        );
+
+        let busy_app = registry(99.0, BucUsage::default());
+        assert_eq!(
+            policy.decide(&busy_app, "app", None),
+            Throttle::Reject(None)
+        );
+        assert_eq!(policy.decide(&busy_app, "other", None), Throttle::Proceed);
+
+        let blocked = registry(
+            10.0,
+            BucUsage {
+                call_count: 100.0,
+                estimated_time_to_regain_access: 10,
+                ..BucUsage::default()
+            },
+        );
+        match policy.decide(&blocked, "app", Some("123")) {
+            Throttle::Reject(Some(retry_after)) => {
+                assert!(retry_after > Duration::from_secs(590))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let overflow = registry(
+            10.0,
+            BucUsage {
+                estimated_time_to_regain_access: u64::MAX,
+                ..BucUsage::default()
+            },
+        );
+        assert!(matches!(
+            policy.decide(&overflow, "app", Some("123")),
+            Throttle::Reject(Some(_))
+        ));
+    }
+
+    #[test]
+    fn test_node_of() {
+        let api = Fbapi::new("v19.0", 10, false).unwrap();
+        assert_eq!(
+            api.node_of("https://graph.facebook.com/v19.0/123/feed"),
+            Some("123".to_owned())
+        );
+        assert_eq!(
+            api.node_of("https://rupload.facebook.com/video-upload/v19.0/456"),
+            Some("456".to_owned())
+        );
+        assert_eq!(api.node_of("https://graph.facebook.com/v19.0/"), None);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::*;
+use futures::future::BoxFuture;
+
+/// Source of access tokens for pages and Instagram users.
+///
+/// Register it with [`FbapiBuilder::token_provider`] and get credentials with
+/// [`Fbapi::credentials_for`]. When a request made with those credentials fails with
+/// an invalid token error (code 190), `refresh` is called and the request is sent again
+/// with the new token. Clones of the credentials share the token, so polling loops that
+/// are still running pick up the refreshed token as well.
+pub trait TokenProvider: Send + Sync {
+    /// Current token for `subject` (page id or Instagram user id).
+    fn token<'a>(&'a self, subject: &'a str) -> BoxFuture<'a, Result<String, FbapiError>>;
+
+    /// New token for `subject` after `expired_token` was rejected.
+    fn refresh<'a>(
+        &'a self,
+        subject: &'a str,
+        expired_token: &'a str,
+    ) -> BoxFuture<'a, Result<String, FbapiError>>;
+}
+
+impl Fbapi {
+    instrument! {
+        api = "credentials_for", node = subject;
+        /// Credentials for `subject` from the registered [`TokenProvider`].
+        pub async fn credentials_for(&self, subject: &str) -> Result<Credentials, FbapiError> {
+            let provider = self
+                .token_provider
+                .as_ref()
+                .ok_or(FbapiError::NoTokenProvider)?;
+            let token = provider.token(subject).await?;
+            Ok(Credentials::new(&token).for_subject(subject))
+        }
+    }
+
+    /// Ask the provider for a new token when `error` is an invalid token error.
+    /// Returns true when the request should be sent again.
+    pub(crate) async fn refresh_token(
+        &self,
+        credentials: &Credentials,
+        used_token: &str,
+        error: &FbapiError,
+    ) -> Result<bool, FbapiError> {
+        if !error.is_invalid_token() {
+            return Ok(false);
+        }
+        let (provider, subject) = match (&self.token_provider, credentials.subject()) {
+            (Some(provider), Some(subject)) => (provider, subject),
+            _ => return Ok(false),
+        };
+        // Another request may already have refreshed the shared token.
+        if credentials.access_token() == used_token {
+            let token = provider.refresh(subject, used_token).await?;
+            credentials.set_access_token(&token);
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counter(AtomicUsize);
+
+    impl TokenProvider for Counter {
+        fn token<'a>(&'a self, subject: &'a str) -> BoxFuture<'a, Result<String, FbapiError>> {
+            Box::pin(async move { Ok(format!("{}-0", subject)) })
+        }
+
+        fn refresh<'a>(
+            &'a self,
+            subject: &'a str,
+            _expired_token: &'a str,
+        ) -> BoxFuture<'a, Result<String, FbapiError>> {
+            let n = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move { Ok(format!("{}-{}", subject, n)) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token() {
+        let api = Fbapi::builder("v19.0")
+            .token_provider(std::sync::Arc::new(Counter(AtomicUsize::new(0))))
+            .build()
+            .unwrap();
+        let credentials = api.credentials_for("page").await.unwrap();
+        let shared = credentials.clone();
+        assert_eq!(credentials.access_token(), "page-0");
+
+        let error = FbapiError::Facebook(json!({"error": {"code": 190}}));
+        assert!(api
+            .refresh_token(&credentials, "page-0", &error)
+            .await
+            .unwrap());
+        assert_eq!(shared.access_token(), "page-1");
+
+        // already refreshed by someone else
+        assert!(api
+            .refresh_token(&credentials, "page-0", &error)
+            .await
+            .unwrap());
+        assert_eq!(shared.access_token(), "page-1");
+
+        let error = FbapiError::Facebook(json!({"error": {"code": 100}}));
+        assert!(!api
+            .refresh_token(&credentials, "page-1", &error)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_multipart_resent_with_refreshed_token() {
+        let api = Fbapi::builder("v19.0")
+            .token_provider(std::sync::Arc::new(Counter(AtomicUsize::new(0))))
+            .redaction(Redaction::disabled())
+            .fault_injection(
+                FaultInjection::new()
+                    .rule(
+                        FaultRule::new(Fault::graph_error(190, Some(463), "Session has expired"))
+                            .first_calls(1),
+                    )
+                    .rule(FaultRule::new(Fault::Response {
+                        status: 200,
+                        body: r#"{"success":true}"#.to_owned(),
+                    })),
+            )
+            .build()
+            .unwrap();
+        let credentials = api
+            .credentials_for("page")
+            .await
+            .unwrap()
+            .with_auth_mode(AuthMode::Parameter);
+        let tokens = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let log_tokens = tokens.clone();
+        let res = api
+            .post_video_thumnail(
+                &credentials,
+                "123",
+                rusoto_core::ByteStream::from(b"jpeg".to_vec()),
+                move |event| {
+                    if let LogEvent::RequestStarted { request, .. } = event {
+                        let token = request.params.iter().find(|(key, _)| key == "access_token");
+                        log_tokens.lock().unwrap().push(token.unwrap().1.clone());
+                    }
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(res["success"], true);
+        assert_eq!(*tokens.lock().unwrap(), vec!["page-0", "page-1"]);
+    }
+}
@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// `x-app-usage` header: percentages of the app's hourly quota.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct AppUsage {
+    #[serde(default)]
+    pub call_count: f64,
+    #[serde(default)]
+    pub total_cputime: f64,
+    #[serde(default)]
+    pub total_time: f64,
+}
+
+/// One entry of the `x-business-use-case-usage` header.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct BucUsage {
+    /// Use case, e.g. `pages`, `instagram` or `ads_management`.
+    #[serde(rename = "type", default)]
+    pub usage_type: String,
+    #[serde(default)]
+    pub call_count: f64,
+    #[serde(default)]
+    pub total_cputime: f64,
+    #[serde(default)]
+    pub total_time: f64,
+    /// Minutes until calls are accepted again; 0 when not throttled.
+    #[serde(default)]
+    pub estimated_time_to_regain_access: u64,
+}
+
+/// `x-ad-account-usage` header.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct AdAccountUsage {
+    #[serde(default)]
+    pub acc_id_util_pct: f64,
+    /// Seconds until the usage is reset.
+    #[serde(default)]
+    pub reset_time_duration: u64,
+    #[serde(default)]
+    pub ads_api_access_tier: Option<String>,
+}
+
+/// Rate limit headers of a response. A header that is missing or not valid json is `None`
+/// (or empty for `business`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub app: Option<AppUsage>,
+    /// `x-business-use-case-usage` entries by business (page, Instagram account, ...) id.
+    pub business: HashMap<String, Vec<BucUsage>>,
+    pub ad_account: Option<AdAccountUsage>,
+}
+
+impl Usage {
+    pub(crate) fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+        fn header<T: serde::de::DeserializeOwned>(
+            headers: &reqwest::header::HeaderMap,
+            name: &str,
+        ) -> Option<T> {
+            let value = headers.get(name)?.to_str().ok()?;
+            serde_json::from_str(value).ok()
+        }
+        Self {
+            app: header(headers, "x-app-usage"),
+            business: header(headers, "x-business-use-case-usage").unwrap_or_default(),
+            ad_account: header(headers, "x-ad-account-usage"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.app.is_none() && self.business.is_empty() && self.ad_account.is_none()
+    }
+}
+
+/// Latest usage reported by Facebook, updated by every response.
+///
+/// Clones share the same data, so one registry can be given to several clients
+/// with [`FbapiBuilder::usage_registry`](crate::FbapiBuilder::usage_registry).
+#[derive(Clone, Debug, Default)]
+pub struct UsageRegistry {
+    inner: Arc<RwLock<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    usage: Usage,
+    /// App usage and when it was reported, by app id.
+    apps: HashMap<String, (AppUsage, Instant)>,
+    business_updated_at: HashMap<String, Instant>,
+}
+
+impl UsageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge the headers of one response to a client of `app_id`: business entries are
+    /// replaced per business id, the app usage of `app_id` and the ad account usage only
+    /// when present.
+    pub fn update(&self, app_id: &str, usage: &Usage) {
+        if usage.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(app) = &usage.app {
+            inner.apps.insert(app_id.to_owned(), (app.clone(), now));
+        }
+        for (id, entries) in &usage.business {
+            inner.usage.business.insert(id.clone(), entries.clone());
+            inner.business_updated_at.insert(id.clone(), now);
+        }
+        if let Some(ad_account) = &usage.ad_account {
+            inner.usage.ad_account = Some(ad_account.clone());
+        }
+    }
+
+    /// Business and ad account usage. App usage is kept per app, see [`UsageRegistry::app`].
+    pub fn snapshot(&self) -> Usage {
+        self.read().usage.clone()
+    }
+
+    pub fn app(&self, app_id: &str) -> Option<AppUsage> {
+        self.read().apps.get(app_id).map(|(app, _)| app.clone())
+    }
+
+    pub fn business(&self, id: &str) -> Vec<BucUsage> {
+        self.read()
+            .usage
+            .business
+            .get(id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn ad_account(&self) -> Option<AdAccountUsage> {
+        self.read().usage.ad_account.clone()
+    }
+
+    /// App usage of `app_id` with the time since it was reported.
+    pub(crate) fn app_with_age(&self, app_id: &str) -> Option<(AppUsage, Duration)> {
+        let (app, updated_at) = self.read().apps.get(app_id)?.clone();
+        Some((app, updated_at.elapsed()))
+    }
+
+    /// Business use case usage of `id` with the time since it was reported.
+    pub(crate) fn business_with_age(&self, id: &str) -> Option<(Vec<BucUsage>, Duration)> {
+        let inner = self.read();
+        Some((
+            inner.usage.business.get(id)?.clone(),
+            inner.business_updated_at.get(id)?.elapsed(),
+        ))
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Inner> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-app-usage",
+            HeaderValue::from_static(r#"{"call_count":28,"total_time":25,"total_cputime":25}"#),
+        );
+        headers.insert(
+            "x-business-use-case-usage",
+            HeaderValue::from_static(
+                r#"{"112233":[{"type":"pages","call_count":100,"total_cputime":4,"total_time":3,"estimated_time_to_regain_access":12}]}"#,
+            ),
+        );
+        headers.insert(
+            "x-ad-account-usage",
+            HeaderValue::from_static(
+                r#"{"acc_id_util_pct":9.67,"reset_time_duration":0,"ads_api_access_tier":"standard_access"}"#,
+            ),
+        );
+        let usage = Usage::from_headers(&headers);
+        assert_eq!(
+            usage.app,
+            Some(AppUsage {
+                call_count: 28.0,
+                total_cputime: 25.0,
+                total_time: 25.0,
+            })
+        );
+        assert_eq!(
+            usage.business["112233"],
+            vec![BucUsage {
+                usage_type: "pages".to_owned(),
+                call_count: 100.0,
+                total_cputime: 4.0,
+                total_time: 3.0,
+                estimated_time_to_regain_access: 12,
+            }]
+        );
+        assert_eq!(usage.ad_account.unwrap().acc_id_util_pct, 9.67);
+    }
+
+    #[test]
+    fn test_registry_update() {
+        let registry = UsageRegistry::new();
+        let mut usage = Usage::default();
+        usage
+            .business
+            .insert("1".to_owned(), vec![BucUsage::default()]);
+        usage.app = Some(AppUsage::default());
+        registry.update("app", &usage);
+        let mut usage = Usage::default();
+        usage
+            .business
+            .insert("2".to_owned(), vec![BucUsage::default()]);
+        registry.clone().update("other", &usage);
+        assert_eq!(registry.app("app"), Some(AppUsage::default()));
+        assert!(registry.app("other").is_none());
+        assert_eq!(registry.business("1").len(), 1);
+        assert_eq!(registry.business("2").len(), 1);
+        assert!(registry.ad_account().is_none());
+    }
+}